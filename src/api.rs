@@ -0,0 +1,208 @@
+//! Library api to embed memflowup into other tools
+//!
+//! Unlike the command handlers these functions do not print any messages (apart from the download progress)
+//! and return structured results instead, the command handlers call into them and render the results.
+
+use std::path::{Path, PathBuf};
+
+use bytes::Bytes;
+use memflow_registry::{
+    storage::PluginMetadata, PluginUri, PluginVariant, SignatureVerifier, MEMFLOW_DEFAULT_REGISTRY,
+};
+
+use crate::{
+    error::{Error, Result},
+    events::{self, Event},
+    registry, util,
+};
+
+pub use crate::util::{InstallSource, LocalPlugin, LocalPluginMetadata};
+
+/// A plugin binary that was downloaded from a registry
+///
+/// The binary is staged in a `.part` file next to its destination until it is installed via [`install`].
+pub struct DownloadedPlugin {
+    /// Registry metadata of the binary, the signature is empty if it was not verified
+    pub metadata: PluginMetadata,
+    /// Path the plugin binary will be installed to
+    pub file_name: PathBuf,
+    pub bytes: Bytes,
+    pub verified: bool,
+    part_file_name: PathBuf,
+}
+
+/// Resolves the plugin uri (e.g. `coredump`, `coredump:0.2.0` or `coredump@sha256:<digest>`) to the matching variant for the os and architecture memflowup is built for.
+///
+/// If no registry is part of the uri the given registry (or the default memflow registry) is used.
+pub async fn resolve(
+    plugin_uri: &str,
+    registry: Option<&str>,
+    include_prerelease: bool,
+) -> Result<(PluginUri, PluginVariant)> {
+    let (plugin_uri, digest) = registry::split_digest(plugin_uri)?;
+    let plugin_uri = PluginUri::with_defaults(
+        plugin_uri,
+        registry.unwrap_or(MEMFLOW_DEFAULT_REGISTRY),
+        "latest",
+    )?;
+    let variant = match digest {
+        Some(digest) => registry::find_by_digest(&plugin_uri, digest).await?,
+        None => registry::find_by_uri(&plugin_uri, false, None, include_prerelease).await?,
+    };
+    Ok((plugin_uri, variant))
+}
+
+/// Downloads, verifies and installs the plugin variant into the given directory.
+///
+/// Passing no verifier skips the signature verification, the plugin is marked as unverified in this case.
+/// Returns the path of the installed plugin binary.
+pub async fn pull(
+    plugin_uri: &PluginUri,
+    variant: &PluginVariant,
+    dir: &Path,
+    prefix: Option<&str>,
+    verifier: Option<&SignatureVerifier>,
+) -> Result<PathBuf> {
+    tokio::fs::create_dir_all(dir).await?;
+
+    let metadata = registry::metadata(plugin_uri, variant).await?;
+    let file_name = util::plugin_file_name_in(dir, &metadata, prefix, true);
+    let mut plugin = download(plugin_uri, variant, metadata, &file_name).await?;
+    plugin.verify(verifier).await?;
+    install(plugin, prefix).await
+}
+
+/// Returns the path the plugin is installed to inside of the given directory, see [`util::plugin_file_name`].
+pub fn plugin_file_name(
+    dir: &Path,
+    metadata: &PluginMetadata,
+    prefix: Option<&str>,
+    include_version: bool,
+) -> PathBuf {
+    util::plugin_file_name_in(dir, metadata, prefix, include_version)
+}
+
+/// Downloads the plugin binary of the variant into a `.part` file next to `file_name` and checks its digest.
+///
+/// A `.part` file left over by an interrupted download is resumed. It is removed again if the digest
+/// of the downloaded binary does not match the registry so a corrupted download is not resumed.
+pub async fn download(
+    plugin_uri: &PluginUri,
+    variant: &PluginVariant,
+    metadata: PluginMetadata,
+    file_name: &Path,
+) -> Result<DownloadedPlugin> {
+    let mut part_file_name = file_name.as_os_str().to_owned();
+    part_file_name.push(".part");
+    let part_file_name = PathBuf::from(part_file_name);
+
+    let bytes =
+        util::download_resumable(&part_file_name, plugin_uri.image(), |offset| async move {
+            let response = registry::download(plugin_uri, variant, offset).await?;
+            events::emit(Event::DownloadStarted {
+                plugin: plugin_uri.image(),
+                bytes: response.content_length().map(|len| len + offset),
+            });
+            Ok(response)
+        })
+        .await?;
+
+    if sha256::digest(bytes.as_ref()) != variant.digest {
+        tokio::fs::remove_file(&part_file_name).await.ok();
+        return Err(Error::Signature("plugin checksum mismatch".to_owned()));
+    }
+
+    Ok(DownloadedPlugin {
+        metadata,
+        file_name: file_name.to_path_buf(),
+        bytes,
+        verified: false,
+        part_file_name,
+    })
+}
+
+impl DownloadedPlugin {
+    /// Verifies the signature of the downloaded binary.
+    ///
+    /// Passing no verifier skips the signature verification, the signature is removed from the metadata
+    /// in this case to mark the plugin as unverified. The `.part` file is removed if the verification fails.
+    pub async fn verify(&mut self, verifier: Option<&SignatureVerifier>) -> Result<()> {
+        let Some(verifier) = verifier else {
+            self.metadata.signature = String::new();
+            self.verified = false;
+            return Ok(());
+        };
+
+        if let Err(err) = verify(verifier, &self.bytes, &self.metadata.signature) {
+            tokio::fs::remove_file(&self.part_file_name).await.ok();
+            return Err(err);
+        }
+        self.verified = true;
+        Ok(())
+    }
+}
+
+/// Checks if the signature is valid for the plugin binary.
+pub fn verify(verifier: &SignatureVerifier, bytes: &[u8], signature: &str) -> Result<()> {
+    verifier
+        .is_valid(bytes, signature)
+        .map_err(|_| Error::Signature("plugin verification failed".to_owned()))
+}
+
+/// Moves the downloaded plugin to its destination and writes its .meta file next to it.
+///
+/// Returns the path of the installed plugin binary.
+pub async fn install(plugin: DownloadedPlugin, prefix: Option<&str>) -> Result<PathBuf> {
+    tokio::fs::rename(&plugin.part_file_name, &plugin.file_name).await?;
+    write_metadata(
+        &plugin.file_name,
+        plugin.metadata,
+        InstallSource::Registry,
+        prefix,
+    )
+    .await?;
+    Ok(plugin.file_name)
+}
+
+/// Installs a locally built plugin binary to `file_name` and writes its .meta file next to it.
+pub async fn install_local(
+    file_name: &Path,
+    content: &[u8],
+    metadata: PluginMetadata,
+    installed_via: InstallSource,
+    prefix: Option<&str>,
+) -> Result<()> {
+    util::write_file_atomic(file_name, content).await?;
+    write_metadata(file_name, metadata, installed_via, prefix).await
+}
+
+/// Writes the .meta file containing all relevant information next to the plugin binary.
+async fn write_metadata(
+    file_name: &Path,
+    metadata: PluginMetadata,
+    installed_via: InstallSource,
+    prefix: Option<&str>,
+) -> Result<()> {
+    // TODO: this does not contain all plugins in this file - allow querying that from memflow-registry as well
+    let metadata = LocalPluginMetadata {
+        prefix: prefix.map(str::to_owned),
+        ..LocalPluginMetadata::new(metadata, installed_via)
+    };
+    util::write_file_atomic(
+        meta_file_name(file_name),
+        serde_json::to_string_pretty(&metadata)?.as_bytes(),
+    )
+    .await
+}
+
+/// Returns the path of the .meta file of the plugin binary.
+pub fn meta_file_name(file_name: &Path) -> PathBuf {
+    let mut meta_file_name = file_name.to_path_buf();
+    meta_file_name.set_extension("meta");
+    meta_file_name
+}
+
+/// Returns all plugins installed in the memflow plugins directory, see [`util::plugins_path`].
+pub async fn local_plugins() -> Result<Vec<LocalPlugin>> {
+    util::local_plugins().await
+}
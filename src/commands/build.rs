@@ -2,24 +2,29 @@
 
 use std::{
     path::{Path, PathBuf},
-    process::{exit, Command, Stdio},
+    process::{exit, Stdio},
 };
 
+use base64::{prelude::BASE64_STANDARD, Engine};
 use chrono::Utc;
-use clap::{Arg, ArgAction, ArgMatches};
+use clap::{value_parser, Arg, ArgAction, ArgMatches};
 use inquire::Confirm;
 use memflow::plugins::plugin_analyzer;
-use memflow_registry::storage::PluginMetadata;
-use tokio::fs::File;
-use tokio::io::AsyncWriteExt;
+use memflow_registry::{storage::PluginMetadata, SignatureGenerator};
+use reqwest::Url;
+use serde::Deserialize;
+use tokio::process::Command;
 
 use crate::{
-    ensure_rust,
+    api, ensure_rust,
     error::{Error, Result},
-    github_api,
-    util::{self, create_temp_dir},
+    events::{self, Event},
+    github_api::{self, RepoProvider},
+    util::{self, create_temp_dir, InstallSource},
 };
 
+use super::config::read_config;
+
 #[inline]
 pub fn metadata() -> clap::Command {
     clap::Command::new("build").args([
@@ -31,47 +36,225 @@ pub fn metadata() -> clap::Command {
             .action(ArgAction::SetTrue),
         Arg::new("branch").long("branch").help("checks out the git repository at this specific branch").action(ArgAction::Set),
         Arg::new("tag").long("tag").help("checks out the git repository at this specific tag").action(ArgAction::Set),
+        Arg::new("pr")
+            .long("pr")
+            .help("checks out the head of this github pull request (e.g. to test a fix from a fork)")
+            .value_parser(value_parser!(u64))
+            .conflicts_with_all(["branch", "tag"])
+            .action(ArgAction::Set),
+        Arg::new("package")
+            .long("package")
+            .value_name("NAME")
+            .help("builds and installs only the given package of a cargo workspace")
+            .action(ArgAction::Set),
         Arg::new("all-features")
             .long("all-features")
             .help("builds the plugin with the --all-features flag")
+            .conflicts_with("features")
+            .action(ArgAction::SetTrue),
+        Arg::new("features")
+            .long("features")
+            .value_name("FEATURES")
+            .help("comma separated list of cargo features to enable (e.g. `--features static`)")
+            .value_delimiter(',')
+            .action(ArgAction::Append),
+        Arg::new("no-default-features")
+            .long("no-default-features")
+            .help("builds the plugin with the --no-default-features flag")
+            .action(ArgAction::SetTrue),
+        Arg::new("env")
+            .long("env")
+            .value_name("KEY=VALUE")
+            .help("sets an additional environment variable for the cargo build (can be used multiple times)")
+            .value_parser(parse_env)
+            .action(ArgAction::Append),
+        Arg::new("no-locked")
+            .long("no-locked")
+            .help("allows cargo to update the Cargo.lock file of the plugin instead of building with --locked")
+            .action(ArgAction::SetTrue),
+        Arg::new("git-token")
+            .long("git-token")
+            .help("access token used to clone private repositories via https (ssh clones use the existing git config and GIT_SSH_COMMAND)")
+            .action(ArgAction::Set),
+        Arg::new("ignore-arch")
+            .long("ignore-arch")
+            .help("installs plugins even if they were built for a different os or architecture (e.g. when cross compiling)")
+            .action(ArgAction::SetTrue),
+        Arg::new("force")
+            .long("force")
+            .help("installs plugins even if their plugin ABI version or architecture does not match this memflowup build")
+            .action(ArgAction::SetTrue),
+        Arg::new("prefix")
+            .long("prefix")
+            .help("prepends a prefix to the installed file names (e.g. to keep separate plugin sets per project)")
+            .value_parser(util::parse_prefix)
+            .action(ArgAction::Set),
+        Arg::new("fail-fast")
+            .long("fail-fast")
+            .help("stops at the first failure instead of continuing with the remaining plugins (for repositories with multiple plugins)")
+            .action(ArgAction::SetTrue),
+        Arg::new("sign")
+            .long("sign")
+            .help("signs the built plugins with the configured `priv_key_file` so they can be pushed without re-signing (default if a private key is configured)")
+            .conflicts_with("no-sign")
+            .action(ArgAction::SetTrue),
+        Arg::new("no-sign")
+            .long("no-sign")
+            .help("does not sign the built plugins even if a private key is configured")
+            .action(ArgAction::SetTrue),
+        Arg::new("dry-run")
+            .long("dry-run")
+            .help("resolves the commit and prints the build plan without downloading the source, building or installing anything")
+            .conflicts_with("keep-source")
+            .action(ArgAction::SetTrue),
+        Arg::new("keep-source")
+            .long("keep-source")
+            .help("keeps the downloaded source code after the build (e.g. to debug build failures)")
             .action(ArgAction::SetTrue),
     ])
 }
 
+/// Parses a `KEY=VALUE` pair for the `--env` argument.
+fn parse_env(env: &str) -> std::result::Result<(String, String), String> {
+    match env.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_owned(), value.to_owned())),
+        _ => Err(format!(
+            "invalid environment variable `{}`, expected KEY=VALUE",
+            env
+        )),
+    }
+}
+
 pub async fn handle(matches: &ArgMatches) -> Result<()> {
+    let dry_run = matches.get_flag("dry-run");
+
     // rust / cargo is required for source builds
-    ensure_rust::ensure_rust().await?;
+    if !dry_run {
+        ensure_rust::ensure_rust().await?;
+    }
 
     let repository_or_path = matches.get_one::<String>("repository_or_path").unwrap();
     let path = matches.get_flag("path");
 
-    let all_features = matches.get_flag("all-features");
+    let cargo_options = CargoBuildOptions {
+        package: matches.get_one::<String>("package").cloned(),
+        all_features: matches.get_flag("all-features"),
+        features: matches
+            .get_many::<String>("features")
+            .unwrap_or_default()
+            .flat_map(|feature| feature.split_whitespace())
+            .map(str::to_owned)
+            .collect(),
+        no_default_features: matches.get_flag("no-default-features"),
+        locked: !matches.get_flag("no-locked"),
+        envs: matches
+            .get_many::<(String, String)>("env")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+    };
+    let git_token = matches.get_one::<String>("git-token").map(String::as_str);
+    let keep_source = matches.get_flag("keep-source");
+    let force = matches.get_flag("force");
+    let ignore_arch = matches.get_flag("ignore-arch") || force;
+    let fail_fast = matches.get_flag("fail-fast");
+    let prefix = matches.get_one::<String>("prefix").map(String::as_str);
+
+    // sign the artifacts right away if a private key is available so they are ready to be pushed
+    let config = read_config().await?;
+    let generator = match (matches.get_flag("no-sign"), &config.priv_key_file) {
+        (true, _) => None,
+        (false, Some(priv_key_file)) => Some(SignatureGenerator::new(priv_key_file)?),
+        (false, None) if matches.get_flag("sign") => {
+            println!(
+                "{} Private key file is required for signing. Configure it via `memflowup config set priv_key_file`",
+                console::style("[X]").bold().dim().red(),
+            );
+            return Err(Error::NotFound("private key file not found".to_owned()));
+        }
+        (false, None) => None,
+    };
+    let install_options = InstallOptions {
+        ignore_arch,
+        force,
+        prefix,
+        generator: generator.as_ref(),
+        versioned_file_names: config.versioned_file_names.unwrap_or(true),
+    };
 
     if !path {
         // download and install from a repository
-        // TODO: support non-github repos
         // TODO: print proper not found error instead of a random error
-        let commit = if let Some(tag) = matches.get_one::<String>("tag") {
-            let tag = github_api::tag(repository_or_path, tag).await?;
-            tag.commit.sha
+        let provider = RepoProvider::from_url(repository_or_path)?;
+        let commit = if let Some(pr) = matches.get_one::<u64>("pr") {
+            if provider != RepoProvider::GitHub {
+                return Err(Error::NotImplemented(
+                    "pull requests can only be built from github repositories".to_owned(),
+                ));
+            }
+            let pr = github_api::pull_request(repository_or_path, *pr).await?;
+            println!(
+                "{} Building pull request #{} \"{}\" by {} ({} at {})",
+                console::style("[-]").bold().dim(),
+                pr.number,
+                pr.title,
+                pr.user.login,
+                pr.head.name,
+                &pr.head.sha[..7.min(pr.head.sha.len())],
+            );
+            pr.head.sha
+        } else if let Some(tag) = matches.get_one::<String>("tag") {
+            provider.tag_commit(repository_or_path, tag).await?
         } else {
             let branch = matches
                 .get_one::<String>("branch")
                 .map(String::as_str)
                 .unwrap_or_else(|| "main");
-            let branch = github_api::branch(repository_or_path, branch).await?;
-            branch.commit.sha
+            provider.branch_commit(repository_or_path, branch).await?
         };
 
-        // create temporary directory (will be dropped when this code path exits)
+        if dry_run {
+            // whether --locked is passed is only known after downloading the source
+            print_build_plan(
+                &format!("{} at commit {}", repository_or_path, commit),
+                &cargo_options.args(true),
+                prefix,
+            );
+            return Ok(());
+        }
+
+        // create temporary directory (will be dropped when this code path exits unless --keep-source is set)
         let temp_dir = create_temp_dir("memflowup_build", &commit).await?;
 
         // run compilation and installation
-        download_repository(repository_or_path, &commit, temp_dir.as_path()).await?;
-        let artifacts = build_artifacts_from_source(&temp_dir, all_features).await?;
-        for artifact in artifacts.iter() {
-            install_artifact(artifact).await.ok();
+        let result: Result<()> = async {
+            download_repository(
+                provider,
+                repository_or_path,
+                &commit,
+                temp_dir.as_path(),
+                git_token,
+            )
+            .await?;
+            let artifacts = build_artifacts_from_source(&temp_dir, &cargo_options).await?;
+            install_artifacts(
+                &artifacts,
+                InstallSource::Source,
+                &install_options,
+                fail_fast,
+            )
+            .await
         }
+        .await;
+
+        if keep_source {
+            println!(
+                "{} Kept source code in: {:?}",
+                console::style("[-]").bold().dim(),
+                temp_dir.persist(),
+            );
+        }
+        result?;
     } else {
         // install from local path
         let path = Path::new(repository_or_path);
@@ -85,19 +268,71 @@ pub async fn handle(matches: &ArgMatches) -> Result<()> {
             ));
         }
 
-        let artifacts = build_artifacts_from_source(path, all_features).await?;
-        for artifact in artifacts.iter() {
-            install_artifact(artifact).await.ok();
+        if dry_run {
+            print_build_plan(
+                &format!("{:?}", path),
+                &cargo_options.args(path.join("Cargo.lock").exists()),
+                prefix,
+            );
+            return Ok(());
         }
+
+        let artifacts = build_artifacts_from_source(path, &cargo_options).await?;
+        install_artifacts(
+            &artifacts,
+            InstallSource::Local,
+            &install_options,
+            fail_fast,
+        )
+        .await?;
     }
 
     Ok(())
 }
 
+/// Prints the source and the cargo command that would be used for `--dry-run`.
+///
+/// The digests and sizes of the plugins are only known after building them.
+fn print_build_plan(source: &str, cargo_args: &str, prefix: Option<&str>) {
+    println!(
+        "{} Dry run, nothing will be downloaded, built or installed.",
+        console::style("[-]").bold().dim(),
+    );
+    println!(
+        "{} Would build plugins from: {}",
+        console::style("[-]").bold().dim(),
+        source,
+    );
+    println!(
+        "{} Would run: cargo {}",
+        console::style("[-]").bold().dim(),
+        cargo_args,
+    );
+    match prefix {
+        Some(prefix) => println!(
+            "{} Would install plugins with prefix `{}` to: {:?}",
+            console::style("[-]").bold().dim(),
+            prefix,
+            util::plugins_path(),
+        ),
+        None => println!(
+            "{} Would install plugins to: {:?}",
+            console::style("[-]").bold().dim(),
+            util::plugins_path(),
+        ),
+    }
+}
+
 /// Downloads the repository to the temporary directory
-async fn download_repository(repository: &str, commit: &str, temp_dir_path: &Path) -> Result<()> {
+async fn download_repository(
+    provider: RepoProvider,
+    repository: &str,
+    commit: &str,
+    temp_dir_path: &Path,
+    git_token: Option<&str>,
+) -> Result<()> {
     match which::which("git") {
-        Ok(_) => download_repository_via_git(repository, commit, temp_dir_path).await,
+        Ok(_) => download_repository_via_git(repository, commit, temp_dir_path, git_token).await,
         Err(_) => {
             println!(
                 "Git was not found on your system. It is either not installed or not in your PATH."
@@ -114,7 +349,9 @@ async fn download_repository(repository: &str, commit: &str, temp_dir_path: &Pat
 
             match ans {
                 Ok(false) | Err(_) => exit(0),
-                _ => download_repository_via_http(repository, commit, temp_dir_path).await,
+                _ => {
+                    download_repository_via_http(provider, repository, commit, temp_dir_path).await
+                }
             }
         }
     }
@@ -124,9 +361,18 @@ async fn download_repository_via_git(
     repository: &str,
     commit: &str,
     temp_dir_path: &Path,
+    git_token: Option<&str>,
 ) -> Result<()> {
     let temp_dir_path_str = temp_dir_path.to_str().ok_or("invalid temporary path")?;
-    Command::new("git")
+
+    // authenticate https requests to the repository host with the access token
+    let git_env = match git_token {
+        Some(token) => git_token_env(repository, token)?,
+        None => Vec::new(),
+    };
+
+    let output = Command::new("git")
+        .envs(git_env.iter().map(|(key, value)| (key, value)))
         .args([
             "clone",
             "--recursive",
@@ -136,22 +382,86 @@ async fn download_repository_via_git(
             temp_dir_path_str,
         ])
         .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
         .output()
+        .await
         .map_err(|_| "unable to clone repository")?;
+    if !output.status.success() {
+        let mut stderr = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        if let Some(token) = git_token {
+            // never leak the token in error messages
+            stderr = stderr.replace(token, "***");
+        }
+        println!(
+            "{} Unable to clone repository {}:\n{}",
+            console::style("[X]").bold().dim().red(),
+            repository,
+            stderr
+        );
+        return Err(Error::IO(format!("unable to clone repository: {}", stderr)));
+    }
 
-    Command::new("git")
+    // the commit might not be part of the shallow clone (e.g. for pull requests from forks),
+    // this is allowed to fail as not all servers support fetching single commits
+    let output = Command::new("git")
+        .current_dir(temp_dir_path)
+        .envs(git_env.iter().map(|(key, value)| (key, value)))
+        .args(["fetch", "--depth", "1", "origin", commit])
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|_| "unable to fetch commit")?;
+    if !output.status.success() {
+        log::debug!("unable to fetch commit {}, using the shallow clone", commit);
+    }
+
+    let output = Command::new("git")
         .current_dir(temp_dir_path)
         .args(["reset", "--hard", commit])
         .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
         .output()
+        .await
         .map_err(|_| "unable to find commit hash")?;
+    util::check_output(output, "git reset")?;
 
     Ok(())
 }
 
+/// Returns the git environment that sends the access token to the host of a https repository url.
+///
+/// The token is passed as an http header via `GIT_CONFIG_*` environment variables,
+/// so it neither shows up in the process arguments nor in the `.git/config` of the checkout.
+fn git_token_env(repository: &str, token: &str) -> Result<Vec<(String, String)>> {
+    let url: Url = repository
+        .parse()
+        .map_err(|_| Error::Parse(format!("invalid repository url `{}`", repository)))?;
+    if url.scheme() != "https" {
+        return Err(Error::Parse(
+            "access tokens can only be used with https repository urls".to_owned(),
+        ));
+    }
+
+    let credentials = BASE64_STANDARD.encode(format!("x-access-token:{}", token));
+    Ok(vec![
+        ("GIT_CONFIG_COUNT".to_owned(), "1".to_owned()),
+        (
+            "GIT_CONFIG_KEY_0".to_owned(),
+            format!("http.{}/.extraHeader", url.origin().ascii_serialization()),
+        ),
+        (
+            "GIT_CONFIG_VALUE_0".to_owned(),
+            format!("Authorization: Basic {}", credentials),
+        ),
+    ])
+}
+
 async fn download_repository_via_http(
+    provider: RepoProvider,
     repository: &str,
     commit: &str,
     temp_dir_path: &Path,
@@ -163,21 +473,90 @@ async fn download_repository_via_http(
         repository,
         commit
     );
-    let response = github_api::download_code_for_commit(repository, commit).await?;
-    let buffer = util::read_response_with_progress(response).await?;
+    // the archive is kept in case the download is interrupted so it can be resumed
+    let part_path = std::env::temp_dir().join(format!("memflowup_source_{}.zip.part", commit));
+    let buffer = util::download_resumable(&part_path, repository, |offset| {
+        provider.download_code_for_commit(repository, commit, offset)
+    })
+    .await?;
 
     // unpack archive
     println!("{} Unpacking source", console::style("[-]").bold().dim(),);
-    util::zip_unpack(buffer.as_ref(), temp_dir_path, 1)?;
+    let result = util::zip_unpack(buffer.as_ref(), temp_dir_path, 1);
+    tokio::fs::remove_file(&part_path).await.ok();
+    result?;
 
     Ok(())
 }
 
+/// Flags passed to `cargo build`
+struct CargoBuildOptions {
+    package: Option<String>,
+    all_features: bool,
+    features: Vec<String>,
+    no_default_features: bool,
+    locked: bool,
+    envs: Vec<(String, String)>,
+}
+
+impl CargoBuildOptions {
+    /// Returns the arguments for `cargo`, `--locked` is only passed if the source contains a Cargo.lock file.
+    fn args(&self, has_lockfile: bool) -> String {
+        // diagnostics are still rendered to stderr, the json messages on stdout are used to find the artifacts
+        let mut args = "build --release --message-format=json-render-diagnostics".to_owned();
+        if let Some(package) = &self.package {
+            args.push_str(&format!(" -p {}", package));
+        }
+        if self.all_features {
+            args.push_str(" --all-features");
+        }
+        if !self.features.is_empty() {
+            args.push_str(&format!(" --features {}", self.features.join(",")));
+        }
+        if self.no_default_features {
+            args.push_str(" --no-default-features");
+        }
+        if self.locked && has_lockfile {
+            // build with the dependency versions the plugin author tested
+            args.push_str(" --locked");
+        }
+        args
+    }
+}
+
+/// Subset of the output of `cargo metadata`
+#[derive(Deserialize)]
+struct CargoMetadata {
+    packages: Vec<CargoPackage>,
+}
+
+#[derive(Deserialize)]
+struct CargoPackage {
+    id: String,
+    name: String,
+}
+
+/// Subset of a `compiler-artifact` message of `cargo build --message-format=json`
+#[derive(Deserialize)]
+struct CargoArtifact {
+    package_id: String,
+    target: CargoTarget,
+    filenames: Vec<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct CargoTarget {
+    kind: Vec<String>,
+}
+
 /// Builds the plugin from the given source path and returns the path of the resulting artifact.
 /// For workspace repos this can return a list of artifacts.
+///
+/// Only cdylibs of the workspace members (or of the selected `--package`) are returned,
+/// cdylibs of dependencies or other files in `target/release` are ignored.
 async fn build_artifacts_from_source(
     source_path: &Path,
-    all_features: bool,
+    options: &CargoBuildOptions,
 ) -> Result<Vec<PathBuf>> {
     // build plugin
     println!(
@@ -185,27 +564,66 @@ async fn build_artifacts_from_source(
         console::style("[-]").bold().dim(),
         source_path,
     );
-    if all_features {
-        let _ = util::cargo("build --release --all-features", source_path)?;
-    } else {
-        let _ = util::cargo("build --release", source_path)?;
+    let has_lockfile = source_path.join("Cargo.lock").exists();
+    if options.locked && !has_lockfile {
+        println!(
+            "{} No Cargo.lock found, dependency versions will be resolved by cargo.",
+            console::style("[-]").bold().dim().yellow(),
+        );
     }
+    let args = options.args(has_lockfile);
+    let output = match util::cargo(&args, source_path, &options.envs).await {
+        Ok(output) => output,
+        Err(err) => {
+            println!(
+                "{} Cargo build failed, see the compiler output above for details.",
+                console::style("[X]").bold().dim().red(),
+            );
+            return Err(err);
+        }
+    };
+
+    // only artifacts of the workspace members (or the selected package) are taken into account
+    let metadata = util::cargo(
+        "metadata --format-version 1 --no-deps",
+        source_path,
+        &options.envs,
+    )
+    .await?;
+    let metadata: CargoMetadata = serde_json::from_slice(&metadata.stdout)?;
+    let package_ids = metadata
+        .packages
+        .iter()
+        .filter(|package| match &options.package {
+            Some(name) => package.name == *name,
+            None => true,
+        })
+        .map(|package| package.id.as_str())
+        .collect::<Vec<_>>();
 
-    // try to find a valid artifact in the build folder
-    let artifact_path = source_path.to_path_buf().join("target").join("release");
-    let paths = std::fs::read_dir(artifact_path)?;
     let mut artifacts = Vec::new();
-    for path in paths.filter_map(|p| p.ok()) {
-        if path.path().is_file() {
-            if let Some(extension) = path.path().extension() {
-                if extension.to_str().unwrap_or_default() == memflow::plugins::plugin_extension() {
-                    println!(
-                        "{} Plugin artifact successfully built: {:?}",
-                        console::style("[=]").bold().dim().green(),
-                        path.path()
-                    );
-                    artifacts.push(path.path());
-                }
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let message = match serde_json::from_str::<serde_json::Value>(line) {
+            Ok(message) if message["reason"] == "compiler-artifact" => message,
+            _ => continue,
+        };
+        let artifact: CargoArtifact = serde_json::from_value(message)?;
+        if !package_ids.contains(&artifact.package_id.as_str())
+            || !artifact.target.kind.iter().any(|kind| kind == "cdylib")
+        {
+            continue;
+        }
+
+        for file_name in artifact.filenames.into_iter() {
+            if file_name.extension().and_then(|e| e.to_str())
+                == Some(memflow::plugins::plugin_extension())
+            {
+                println!(
+                    "{} Plugin artifact successfully built: {:?}",
+                    console::style("[=]").bold().dim().green(),
+                    file_name
+                );
+                artifacts.push(file_name);
             }
         }
     }
@@ -225,7 +643,64 @@ async fn build_artifacts_from_source(
     }
 }
 
-async fn install_artifact(artifact_path: &Path) -> Result<()> {
+/// Options for installing build artifacts into the plugins directory
+struct InstallOptions<'a> {
+    ignore_arch: bool,
+    force: bool,
+    prefix: Option<&'a str>,
+    generator: Option<&'a SignatureGenerator>,
+    /// Includes the plugin version in the installed file names
+    versioned_file_names: bool,
+}
+
+/// Installs all build artifacts, a failing artifact does not abort the others unless `fail_fast` is set.
+///
+/// Every failure is reported, an error is returned afterwards if any artifact failed to install.
+async fn install_artifacts(
+    artifacts: &[PathBuf],
+    installed_via: InstallSource,
+    options: &InstallOptions<'_>,
+    fail_fast: bool,
+) -> Result<()> {
+    let mut failed = 0;
+    for artifact in artifacts.iter() {
+        let result = install_artifact(artifact, installed_via, options).await;
+        if let Err(err) = result {
+            if events::is_json() {
+                events::emit(Event::Error {
+                    plugin: &artifact.to_string_lossy(),
+                    message: err.to_string(),
+                });
+            } else {
+                println!(
+                    "{} Failed to install artifact {:?}: {}",
+                    console::style("[X]").bold().dim().red(),
+                    artifact,
+                    err
+                );
+            }
+            if fail_fast {
+                return Err(err);
+            }
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(Error::Unknown(format!(
+            "{} of {} artifacts failed to install",
+            failed,
+            artifacts.len()
+        )));
+    }
+    Ok(())
+}
+
+async fn install_artifact(
+    artifact_path: &Path,
+    installed_via: InstallSource,
+    options: &InstallOptions<'_>,
+) -> Result<()> {
     // parse the plugins descriptor
     let artifact_content = tokio::fs::read(artifact_path).await?;
     let descriptors = plugin_analyzer::parse_descriptors(&artifact_content)?;
@@ -243,15 +718,66 @@ async fn install_artifact(artifact_path: &Path) -> Result<()> {
         ));
     }
 
+    // memflow will refuse to load plugins built for a different os or architecture
+    let descriptor = &descriptors[0];
+    let foreign_arch = descriptor.file_type != util::host_file_type()
+        || descriptor.architecture != util::host_architecture();
+    if foreign_arch && !options.ignore_arch {
+        let plugin_arch = format!("{:?}/{:?}", descriptor.file_type, descriptor.architecture)
+            .to_ascii_lowercase();
+        let host_arch = format!(
+            "{:?}/{:?}",
+            util::host_file_type(),
+            util::host_architecture()
+        )
+        .to_ascii_lowercase();
+        println!(
+            "{} Plugin {:?} was built for {} but this system is {}. Use `--ignore-arch` to install it anyway.",
+            console::style("[X]").bold().dim().red(),
+            artifact_path,
+            plugin_arch,
+            host_arch,
+        );
+        return Err(Error::Parse(format!(
+            "architecture mismatch: plugin is {}, host is {}",
+            plugin_arch, host_arch
+        )));
+    }
+
+    // memflow refuses to load plugins with a different plugin ABI version as well
+    let plugin_version = memflow::plugins::MEMFLOW_PLUGIN_VERSION;
+    if descriptor.plugin_version != plugin_version && !options.force {
+        println!(
+            "{} Plugin {:?} was built for plugin ABI version {} but this memflowup expects version {}. Use `--force` to install it anyway.",
+            console::style("[X]").bold().dim().red(),
+            artifact_path,
+            descriptor.plugin_version,
+            plugin_version,
+        );
+        return Err(Error::Parse(format!(
+            "plugin ABI version mismatch: plugin is {}, host is {}",
+            descriptor.plugin_version, plugin_version
+        )));
+    }
+
     let metadata = PluginMetadata {
         digest: sha256::digest(&artifact_content),
-        signature: String::new(),
+        // the signature is stored in the .meta file and reused by `push`
+        signature: match options.generator {
+            Some(generator) => generator.clone().sign(&artifact_content)?,
+            None => String::new(),
+        },
         created_at: Utc::now().naive_utc(),
         descriptors,
     };
 
     // construct destination file_name in memflowup registry
-    let file_name = util::plugin_file_name(&metadata);
+    // plugins for other architectures contain the architecture in their file name so they can coexist
+    let file_name = if foreign_arch {
+        util::plugin_file_name_with_arch(&metadata, options.prefix, options.versioned_file_names)
+    } else {
+        util::plugin_file_name(&metadata, options.prefix, options.versioned_file_names)
+    };
     if file_name.exists() {
         println!(
             "{} Plugin already exists, overwriting.",
@@ -259,27 +785,31 @@ async fn install_artifact(artifact_path: &Path) -> Result<()> {
         );
     }
 
-    // write file
-    let mut file = File::create(&file_name).await?;
-    file.write_all(&artifact_content).await?;
-    file.flush().await?;
-
-    println!(
-        "{} Wrote plugin to: {:?}",
-        console::style("[=]").bold().dim().green(),
-        file_name.as_os_str(),
-    );
-
-    // store .meta file of plugin containing all relevant information
-    // TODO: this does not contain all plugins in this file - allow querying that from memflow-registry as well
-    let mut file_name = file_name.clone();
-    file_name.set_extension("meta");
-    tokio::fs::write(&file_name, serde_json::to_string_pretty(&metadata)?).await?;
-
-    println!(
-        "{} Wrote plugin metadata to: {:?}",
-        console::style("[=]").bold().dim().green(),
-        file_name.as_os_str(),
-    );
+    // write file and its .meta file
+    let plugin = metadata.descriptors[0].name.clone();
+    let digest = metadata.digest.clone();
+    api::install_local(
+        &file_name,
+        &artifact_content,
+        metadata,
+        installed_via,
+        options.prefix,
+    )
+    .await?;
+
+    events::emit(Event::Installed {
+        plugin: &plugin,
+        digest: &digest,
+        path: &file_name,
+        verified: false,
+    });
+
+    if !events::is_json() {
+        println!(
+            "{} Wrote plugin metadata to: {:?}",
+            console::style("[=]").bold().dim().green(),
+            api::meta_file_name(&file_name).as_os_str(),
+        );
+    }
     Ok(())
 }
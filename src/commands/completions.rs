@@ -0,0 +1,32 @@
+//! Clap subcommand to generate shell completion scripts
+
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use clap_complete::Shell;
+
+use crate::error::Result;
+
+#[inline]
+pub fn metadata() -> Command {
+    Command::new("completions")
+        .about("prints the shell completion script for memflowup to stdout")
+        .after_help(
+            "Where to put the generated script:
+  bash:       memflowup completions bash > ~/.local/share/bash-completion/completions/memflowup
+  zsh:        memflowup completions zsh > ~/.zfunc/_memflowup (the directory has to be part of your $fpath)
+  fish:       memflowup completions fish > ~/.config/fish/completions/memflowup.fish
+  powershell: add `memflowup completions powershell | Out-String | Invoke-Expression` to your $PROFILE",
+        )
+        .args([Arg::new("shell")
+            .required(true)
+            .value_parser(value_parser!(Shell))
+            .help("shell to generate the completion script for")
+            .action(ArgAction::Set)])
+}
+
+/// Writes the completion script for the full memflowup command tree to stdout.
+pub fn handle(matches: &ArgMatches, command: &mut Command) -> Result<()> {
+    let shell = *matches.get_one::<Shell>("shell").unwrap();
+    let name = command.get_name().to_owned();
+    clap_complete::generate(shell, command, name, &mut std::io::stdout());
+    Ok(())
+}
@@ -1,27 +1,75 @@
 //! Clap subcommand to configure memflowup
 
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use clap::{Arg, ArgMatches, Command};
 use memflow_registry::{SignatureGenerator, SignatureVerifier, MEMFLOW_DEFAULT_REGISTRY};
+use reqwest::Url;
 use serde::{Deserialize, Serialize};
 
 use crate::{
     error::{Error, Result},
-    util,
+    registry, util,
 };
 
-pub const CONFIG_KEYS: [&str; 4] = ["registry", "token", "pub_key_file", "priv_key_file"];
+pub const CONFIG_KEYS: [&str; 11] = [
+    "registry",
+    "token",
+    "github_token",
+    "pub_key_file",
+    "priv_key_file",
+    "signer_command",
+    "default_pub_key",
+    "include_prerelease",
+    "connect_timeout",
+    "request_timeout",
+    "versioned_file_names",
+];
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Config {
     pub registry: Option<String>,
     pub token: Option<String>,
+    /// Token used to authenticate github api requests, the `GITHUB_TOKEN` environment variable takes precedence
+    pub github_token: Option<String>,
     pub pub_key_file: Option<PathBuf>,
     pub priv_key_file: Option<PathBuf>,
+    /// External command which receives the sha256 digest of a plugin on stdin and prints its signature (hex encoded DER)
+    pub signer_command: Option<String>,
+    /// Public key (PEM) which replaces the bundled memflow registry key as the default verifier
+    pub default_pub_key: Option<String>,
+    pub include_prerelease: Option<bool>,
+    /// Time to wait for a connection to be established (e.g. `30s`)
+    pub connect_timeout: Option<String>,
+    /// Time to wait for new data on an established connection (e.g. `1m`)
+    pub request_timeout: Option<String>,
+    /// Includes the plugin version in the file names of installed plugins (enabled by default)
+    pub versioned_file_names: Option<bool>,
 }
 
 impl Config {
+    /// Returns the registry token with all but the first characters masked (e.g. for printing it).
+    pub fn masked_token(&self) -> Option<String> {
+        self.token.as_deref().map(mask_token)
+    }
+
+    /// Returns the configured connect timeout, invalid values are ignored.
+    pub fn connect_timeout(&self) -> Option<Duration> {
+        self.connect_timeout
+            .as_deref()
+            .and_then(|timeout| humantime::parse_duration(timeout).ok())
+    }
+
+    /// Returns the configured request timeout, invalid values are ignored.
+    pub fn request_timeout(&self) -> Option<Duration> {
+        self.request_timeout
+            .as_deref()
+            .and_then(|timeout| humantime::parse_duration(timeout).ok())
+    }
+
     #[inline]
     pub fn get(&self, key: &str) -> Result<Option<&str>> {
         match key {
@@ -29,6 +77,7 @@ impl Config {
                 self.registry.as_deref().unwrap_or(MEMFLOW_DEFAULT_REGISTRY),
             )),
             "token" => Ok(self.token.as_deref()),
+            "github_token" => Ok(self.github_token.as_deref()),
             "pub_key_file" => Ok(self
                 .pub_key_file
                 .as_ref()
@@ -37,6 +86,20 @@ impl Config {
                 .priv_key_file
                 .as_ref()
                 .map(|p| p.as_os_str().to_str().unwrap())),
+            "signer_command" => Ok(self.signer_command.as_deref()),
+            "default_pub_key" => Ok(self.default_pub_key.as_deref()),
+            "include_prerelease" => {
+                Ok(self
+                    .include_prerelease
+                    .map(|v| if v { "true" } else { "false" }))
+            }
+            "connect_timeout" => Ok(self.connect_timeout.as_deref()),
+            "request_timeout" => Ok(self.request_timeout.as_deref()),
+            "versioned_file_names" => {
+                Ok(self
+                    .versioned_file_names
+                    .map(|v| if v { "true" } else { "false" }))
+            }
             _ => Err(Error::NotFound(format!("option `{}` is invalid", key))),
         }
     }
@@ -45,13 +108,39 @@ impl Config {
     pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
         match key {
             "registry" => {
-                self.registry = Some(value.to_owned());
+                // urls with an explicit scheme are validated as is (e.g. to catch typos like `htps://`),
+                // bare hosts (e.g. `registry.example.com`) are normalized to a https url
+                let url = if value.contains("://") {
+                    Url::parse(value).map_err(|err| {
+                        Error::Parse(format!("invalid registry url `{}`: {}", value, err))
+                    })?
+                } else {
+                    registry::registry_url(Some(value))?
+                };
+                if url.scheme() != "http" && url.scheme() != "https" {
+                    return Err(Error::Parse(format!(
+                        "unsupported scheme `{}` in registry url `{}`, only http and https are supported",
+                        url.scheme(),
+                        value
+                    )));
+                }
+                if url.host_str().unwrap_or_default().is_empty() {
+                    return Err(Error::Parse(format!(
+                        "registry url `{}` does not contain a host",
+                        value
+                    )));
+                }
+                self.registry = Some(url.as_str().trim_end_matches('/').to_owned());
                 Ok(())
             }
             "token" => {
                 self.token = Some(value.to_owned());
                 Ok(())
             }
+            "github_token" => {
+                self.github_token = Some(value.to_owned());
+                Ok(())
+            }
             "pub_key_file" => {
                 let path = Path::new(value);
                 if path.exists() {
@@ -84,6 +173,46 @@ impl Config {
                     Err(Error::NotFound("Key file does not exist".to_owned()))
                 }
             }
+            "signer_command" => {
+                self.signer_command = Some(value.to_owned());
+                Ok(())
+            }
+            "default_pub_key" => match SignatureVerifier::with_str(value) {
+                Ok(_) => {
+                    self.default_pub_key = Some(value.to_owned());
+                    Ok(())
+                }
+                Err(_) => Err(Error::Parse("Value is not a valid public key".to_owned())),
+            },
+            "include_prerelease" => {
+                self.include_prerelease = Some(value.parse().map_err(|_| {
+                    Error::Parse("Value must be either `true` or `false`".to_owned())
+                })?);
+                Ok(())
+            }
+            "versioned_file_names" => {
+                self.versioned_file_names = Some(value.parse().map_err(|_| {
+                    Error::Parse("Value must be either `true` or `false`".to_owned())
+                })?);
+                Ok(())
+            }
+            "connect_timeout" | "request_timeout" => {
+                let timeout = humantime::parse_duration(value).map_err(|err| {
+                    Error::Parse(format!(
+                        "invalid duration `{}` (e.g. `30s`): {}",
+                        value, err
+                    ))
+                })?;
+                if timeout.is_zero() {
+                    return Err(Error::Parse("Timeout must not be zero".to_owned()));
+                }
+                if key == "connect_timeout" {
+                    self.connect_timeout = Some(value.to_owned());
+                } else {
+                    self.request_timeout = Some(value.to_owned());
+                }
+                Ok(())
+            }
             _ => Err(Error::NotFound(format!("option `{}` is invalid", key))),
         }
     }
@@ -99,6 +228,10 @@ impl Config {
                 self.token = None;
                 Ok(())
             }
+            "github_token" => {
+                self.github_token = None;
+                Ok(())
+            }
             "pub_key_file" => {
                 self.pub_key_file = None;
                 Ok(())
@@ -107,6 +240,30 @@ impl Config {
                 self.priv_key_file = None;
                 Ok(())
             }
+            "signer_command" => {
+                self.signer_command = None;
+                Ok(())
+            }
+            "default_pub_key" => {
+                self.default_pub_key = None;
+                Ok(())
+            }
+            "include_prerelease" => {
+                self.include_prerelease = None;
+                Ok(())
+            }
+            "versioned_file_names" => {
+                self.versioned_file_names = None;
+                Ok(())
+            }
+            "connect_timeout" => {
+                self.connect_timeout = None;
+                Ok(())
+            }
+            "request_timeout" => {
+                self.request_timeout = None;
+                Ok(())
+            }
             _ => Err(Error::NotFound(format!("option `{}` is invalid", key))),
         }
     }
@@ -118,6 +275,7 @@ pub fn metadata() -> Command {
         .subcommand_required(true)
         .subcommands([
             Command::new("get").args([Arg::new("key").help("configuration entry key")]),
+            Command::new("list").alias("ls"),
             Command::new("set").args([
                 Arg::new("key")
                     .help("configuration entry key")
@@ -127,6 +285,8 @@ pub fn metadata() -> Command {
             Command::new("unset").args([Arg::new("key")
                 .help("configuration entry key")
                 .required(true)]),
+            Command::new("validate"),
+            Command::new("reset"),
         ])
 }
 
@@ -147,35 +307,20 @@ pub async fn handle(matches: &ArgMatches) -> Result<()> {
                     _ => (),
                 }
             } else {
-                println!("registry = \"{}\"", config.registry.unwrap_or_default());
-
-                let token = config.token.unwrap_or_default();
-                let token = if token.len() > 6 {
-                    format!(
-                        "{}{}",
-                        &token[..4],
-                        token[4..].chars().map(|_| '*').collect::<String>()
-                    )
-                } else {
-                    token.chars().map(|_| '*').collect()
-                };
-                println!("token = \"{}\"", token);
-
-                println!(
-                    "pub_key_file = {:?}",
-                    config.pub_key_file.unwrap_or_default()
-                );
-                println!(
-                    "priv_key_file = {:?}",
-                    config.priv_key_file.unwrap_or_default()
-                );
+                print_config(&config);
             }
             Ok(())
         }
+        Some(("list", _)) => {
+            let config = read_config().await?;
+            print_config(&config);
+            Ok(())
+        }
         Some(("set", matches)) | Some(("unset", matches)) => {
             let key = matches.get_one::<String>("key").unwrap();
 
-            let mut config = read_config().await?;
+            // a broken config file is not silently replaced by the defaults here
+            let mut config = read_config_strict().await?;
 
             let result = if let Some(value) = matches.get_one::<String>("value") {
                 config.set(key, value)
@@ -193,11 +338,86 @@ pub async fn handle(matches: &ArgMatches) -> Result<()> {
 
             write_config(config).await
         }
+        Some(("validate", _)) => match read_config_strict().await {
+            Ok(_) => {
+                println!(
+                    "{} Config file {:?} is valid.",
+                    console::style("[=]").bold().dim().green(),
+                    util::config_file_path(),
+                );
+                Ok(())
+            }
+            Err(err) => {
+                println!(
+                    "{} Config file {:?} is invalid: {}",
+                    console::style("[X]").bold().dim().red(),
+                    util::config_file_path(),
+                    err
+                );
+                Err(err)
+            }
+        },
+        Some(("reset", _)) => {
+            write_config(Config::default()).await?;
+            println!(
+                "{} Config file {:?} was reset to the default configuration.",
+                console::style("[=]").bold().dim().green(),
+                util::config_file_path(),
+            );
+            Ok(())
+        }
         _ => unreachable!(),
     }
 }
 
+/// Prints all config keys with their values, tokens are masked.
+fn print_config(config: &Config) {
+    for key in CONFIG_KEYS.iter() {
+        let value = match *key {
+            "token" => config.masked_token(),
+            "github_token" => config.github_token.as_deref().map(mask_token),
+            key => config.get(key).ok().flatten().map(str::to_owned),
+        };
+        match value {
+            Some(value) => println!("{} = {:?}", key, value),
+            None => println!("{} = (unset)", key),
+        }
+    }
+}
+
+/// Only shows the first characters of longer tokens so they can still be told apart.
+fn mask_token(token: &str) -> String {
+    let len = token.chars().count();
+    if len > 6 {
+        let visible: String = token.chars().take(4).collect();
+        visible + &"*".repeat(len - 4)
+    } else {
+        "*".repeat(len)
+    }
+}
+
+/// Reads the config file.
+///
+/// An invalid config file does not prevent other commands from working,
+/// a warning is printed and the default configuration is used instead.
 pub async fn read_config() -> Result<Config> {
+    match read_config_strict().await {
+        Ok(config) => Ok(config),
+        Err(Error::Parse(err)) => {
+            eprintln!(
+                "{} Config file {:?} is invalid ({}), using the default configuration. Run `memflowup config validate` for details or `memflowup config reset` to reset it.",
+                console::style("[-]").bold().dim().yellow(),
+                util::config_file_path(),
+                err
+            );
+            Ok(Config::default())
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Reads the config file and fails if it cannot be parsed.
+pub async fn read_config_strict() -> Result<Config> {
     let content = tokio::fs::read_to_string(util::config_file_path()).await?;
     let config: Config = serde_json::from_str(&content)?;
     Ok(config)
@@ -207,3 +427,68 @@ pub async fn write_config(config: Config) -> Result<()> {
     let content = serde_json::to_string(&config)?;
     Ok(tokio::fs::write(util::config_file_path(), content.as_bytes()).await?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mask_short_token() {
+        assert_eq!(mask_token(""), "");
+        assert_eq!(mask_token("abcdef"), "******");
+    }
+
+    #[test]
+    fn mask_long_token() {
+        assert_eq!(mask_token("abcdefgh"), "abcd****");
+        let config = Config {
+            token: Some("secret-token".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(config.masked_token().as_deref(), Some("secr********"));
+    }
+
+    #[test]
+    fn mask_non_ascii_token() {
+        assert_eq!(mask_token("äöüß"), "****");
+        assert_eq!(mask_token("äöüßtoken"), "äöüß*****");
+    }
+
+    #[test]
+    fn set_registry_https_url() {
+        let mut config = Config::default();
+        config
+            .set("registry", "https://registry.example.com")
+            .unwrap();
+        assert_eq!(
+            config.registry.as_deref(),
+            Some("https://registry.example.com")
+        );
+    }
+
+    #[test]
+    fn set_registry_bare_host() {
+        let mut config = Config::default();
+        config.set("registry", "registry.example.com/").unwrap();
+        assert_eq!(
+            config.registry.as_deref(),
+            Some("https://registry.example.com")
+        );
+    }
+
+    #[test]
+    fn set_registry_unsupported_scheme() {
+        let mut config = Config::default();
+        assert!(config
+            .set("registry", "ftp://registry.example.com")
+            .is_err());
+        assert_eq!(config.registry, None);
+    }
+
+    #[test]
+    fn set_registry_without_host() {
+        let mut config = Config::default();
+        assert!(config.set("registry", "https://").is_err());
+        assert_eq!(config.registry, None);
+    }
+}
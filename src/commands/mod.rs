@@ -1,9 +1,43 @@
 pub mod build;
+pub mod completions;
 pub mod config;
 pub mod plugins;
 pub mod pull;
 pub mod push;
 pub mod registry;
+pub mod search;
+pub mod update;
+
+use clap::{Arg, ArgAction, ArgMatches};
+use serde::Serialize;
+
+use crate::error::Result;
+
+/// Output format of plugin listings, `json` is meant to be consumed by scripts.
+#[inline]
+fn output_arg() -> Arg {
+    Arg::new("output")
+        .short('o')
+        .long("output")
+        .value_parser(["table", "json"])
+        .default_value("table")
+        .global(true)
+        .help("output format of listings")
+        .action(ArgAction::Set)
+}
+
+#[inline]
+fn is_json_output(matches: &ArgMatches) -> bool {
+    matches
+        .get_one::<String>("output")
+        .map(|f| f == "json")
+        .unwrap_or_default()
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
 
 #[allow(clippy::print_literal)]
 #[inline]
@@ -13,3 +47,27 @@ fn print_plugin_versions_header() {
         "NAME", "VERSION", "ARCH", "ABI", "DIGEST", "DIGEST_LONG", "CREATED"
     );
 }
+
+#[allow(clippy::print_literal)]
+#[inline]
+fn print_local_plugin_versions_header(long: bool) {
+    if long {
+        println!(
+            "{0: <16} {1: <16} {2: <12} {3: <4} {4: <8} {5: <65} {6: <19} {7: <13} {8:}",
+            "NAME",
+            "VERSION",
+            "ARCH",
+            "ABI",
+            "DIGEST",
+            "DIGEST_LONG",
+            "CREATED",
+            "INSTALLED_VIA",
+            "DESCRIPTION"
+        );
+    } else {
+        println!(
+            "{0: <16} {1: <16} {2: <12} {3: <4} {4: <8} {5: <65} {6: <19} {7:}",
+            "NAME", "VERSION", "ARCH", "ABI", "DIGEST", "DIGEST_LONG", "CREATED", "INSTALLED_VIA"
+        );
+    }
+}
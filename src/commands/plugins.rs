@@ -1,39 +1,298 @@
 //! Clap subcommand to list all installed plugins
 
-use std::collections::HashSet;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    io::IsTerminal,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use clap::{Arg, ArgAction, ArgMatches, Command};
-use memflow_registry::storage::PluginMetadata;
+use chrono::{DateTime, SubsecRound, Utc};
+use clap::{builder::RangedU64ValueParser, Arg, ArgAction, ArgMatches, Command};
+use futures_util::StreamExt;
+use inquire::Confirm;
+use memflow::plugins::{
+    plugin_analyzer::{self, PluginArchitecture, PluginFileType},
+    MEMFLOW_PLUGIN_VERSION,
+};
+use memflow_registry::{storage::PluginMetadata, PluginVariant};
+use serde::Serialize;
 
 use crate::{
-    error::Result,
-    util::{self, LocalPlugin},
+    error::{Error, Result},
+    registry,
+    util::{self, InstallSource, LocalPlugin, LocalPluginMetadata},
 };
 
+use super::config::read_config;
+
 #[inline]
 pub fn metadata() -> Command {
     Command::new("plugins")
         .subcommand_required(true)
+        .args([super::output_arg()])
         .subcommands([
-            Command::new("list")
-                .alias("ls")
-                .args([Arg::new("plugin_name")
+            Command::new("list").alias("ls").args([
+                Arg::new("plugin_name")
                     .help("name of the plugin as an additional filter")
-                    .action(ArgAction::Set)]),
-            Command::new("clean").alias("purge"),
-            Command::new("remove")
-                .alias("rm")
-                .args([Arg::new("plugin_uri")
+                    .action(ArgAction::Set),
+                Arg::new("source-only")
+                    .long("source-only")
+                    .help("only lists plugins that were built from source")
+                    .conflicts_with("registry-only")
+                    .action(ArgAction::SetTrue),
+                Arg::new("registry-only")
+                    .long("registry-only")
+                    .help("only lists plugins that were pulled from a registry")
+                    .action(ArgAction::SetTrue),
+                Arg::new("prefix")
+                    .long("prefix")
+                    .help("only lists plugins that were installed with the given file name prefix")
+                    .action(ArgAction::Set),
+                Arg::new("arch")
+                    .long("arch")
+                    .value_parser(registry::ARCHITECTURES)
+                    .help("only lists plugins for the given architecture")
+                    .action(ArgAction::Set),
+                Arg::new("file-type")
+                    .long("file-type")
+                    .value_parser(registry::FILE_TYPES)
+                    .help("only lists plugins with the given binary file type")
+                    .action(ArgAction::Set),
+                Arg::new("long")
+                    .short('l')
+                    .long("long")
+                    .help("shows the long listing, including the plugin description")
+                    .action(ArgAction::SetTrue),
+                Arg::new("outdated")
+                    .long("outdated")
+                    .help("only lists plugins for which a newer variant is available in the registry")
+                    .conflicts_with("long")
+                    .action(ArgAction::SetTrue),
+                Arg::new("registry")
+                    .short('r')
+                    .long("registry")
+                    .help("registry to compare the installed plugins with")
+                    .requires("outdated")
+                    .action(ArgAction::Set),
+            ]),
+            Command::new("clean").alias("purge").args([
+                Arg::new("keep")
+                    .long("keep")
+                    .alias("max-versions")
+                    .value_parser(RangedU64ValueParser::<usize>::new().range(1..))
+                    .default_value("1")
+                    .help("the amount of most recent versions to keep for each plugin")
+                    .action(ArgAction::Set),
+                Arg::new("dry-run")
+                    .long("dry-run")
+                    .help("only prints the plugins that would be removed and why")
+                    .action(ArgAction::SetTrue),
+                Arg::new("yes")
+                    .short('y')
+                    .long("yes")
+                    .help("deletes the plugins without asking for confirmation (required if stdin is not a terminal)")
+                    .action(ArgAction::SetTrue),
+            ]),
+            Command::new("prune").args([
+                Arg::new("older-than")
+                    .long("older-than")
+                    .required(true)
+                    .value_parser(humantime::parse_duration)
+                    .help("removes plugin versions installed before the given duration (e.g. `30d`), the newest version of each plugin is always kept")
+                    .action(ArgAction::Set),
+                Arg::new("dry-run")
+                    .long("dry-run")
+                    .help("only prints the plugins that would be removed and why")
+                    .action(ArgAction::SetTrue),
+                Arg::new("yes")
+                    .short('y')
+                    .long("yes")
+                    .help("deletes the plugins without asking for confirmation (required if stdin is not a terminal)")
+                    .action(ArgAction::SetTrue),
+            ]),
+            Command::new("info").args([Arg::new("plugin_uri")
+                .required(true)
+                .help("uri of the plugin in the form of [registry]/[name]:[version]")
+                .action(ArgAction::Set)]),
+            Command::new("repair"),
+            Command::new("verify").args([
+                Arg::new("plugin_name")
+                    .help("name of the plugin as an additional filter")
+                    .action(ArgAction::Set),
+                Arg::new("prefix")
+                    .long("prefix")
+                    .help("only verifies plugins that were installed with the given file name prefix")
+                    .action(ArgAction::Set),
+                Arg::new("fix")
+                    .long("fix")
+                    .help("removes the verified plugins that failed the verification (same as the orphan removal in `plugins clean`, limited to the name and prefix filter)")
+                    .action(ArgAction::SetTrue),
+                Arg::new("yes")
+                    .short('y')
+                    .long("yes")
+                    .help("deletes the plugins without asking for confirmation (required if stdin is not a terminal)")
+                    .requires("fix")
+                    .action(ArgAction::SetTrue),
+            ]),
+            Command::new("which").args([Arg::new("plugin_uri")
+                .required(true)
+                .help("uri of the plugin in the form of [registry]/[name]:[version]")
+                .action(ArgAction::Set)]),
+            Command::new("remove").alias("rm").args([
+                Arg::new("plugin_uri")
                     .help("uri of the plugin in the form of [registry]/[name]:[version]")
-                    .action(ArgAction::Append)]),
+                    .required_unless_present("prefix")
+                    .action(ArgAction::Append),
+                Arg::new("prefix")
+                    .long("prefix")
+                    .help(
+                        "only removes plugins that were installed with the given file name prefix",
+                    )
+                    .action(ArgAction::Set),
+                Arg::new("yes")
+                    .short('y')
+                    .long("yes")
+                    .help("deletes the plugins without asking for confirmation (required if stdin is not a terminal)")
+                    .action(ArgAction::SetTrue),
+            ]),
+            // same as the top level `update` command
+            super::update::metadata(),
         ])
 }
 
 pub async fn handle(matches: &ArgMatches) -> Result<()> {
     match matches.subcommand() {
         Some(("list", matches)) => {
-            super::print_plugin_versions_header();
-            list_local_plugins(matches.get_one::<String>("plugin_name").map(String::as_str)).await
+            let installed_via = if matches.get_flag("source-only") {
+                Some(&[InstallSource::Source, InstallSource::Local][..])
+            } else if matches.get_flag("registry-only") {
+                Some(&[InstallSource::Registry][..])
+            } else {
+                None
+            };
+
+            let filter = ListFilter {
+                plugin_name: matches.get_one::<String>("plugin_name").map(String::as_str),
+                prefix: matches.get_one::<String>("prefix").map(String::as_str),
+                installed_via,
+                architecture: matches.get_one::<String>("arch").map(String::as_str),
+                file_type: matches.get_one::<String>("file-type").map(String::as_str),
+            };
+            let long = matches.get_flag("long");
+            let json = super::is_json_output(matches);
+
+            if matches.get_flag("outdated") {
+                let config = read_config().await?;
+                let registry = matches
+                    .get_one::<String>("registry")
+                    .map(String::as_str)
+                    .or(config.registry.as_deref());
+                let include_prerelease = config.include_prerelease.unwrap_or_default();
+                return list_outdated_plugins(filter, registry, include_prerelease, json).await;
+            }
+
+            if !json {
+                super::print_local_plugin_versions_header(long);
+            }
+            list_local_plugins(filter, long, json).await
+        }
+        Some(("prune", matches)) => {
+            let older_than = *matches.get_one::<Duration>("older-than").unwrap();
+            let plugins = util::local_plugins().await?;
+            let pruned = plugins_older_than(&plugins, older_than)?;
+
+            for plugin in pruned.iter() {
+                println!(
+                    "{} Would delete plugin: {:?} (created at {})",
+                    console::style("[-]").bold().dim(),
+                    plugin.plugin_file_name.as_os_str(),
+                    plugin.created_at
+                );
+            }
+            if matches.get_flag("dry-run") {
+                println!(
+                    "{} Dry run, {} plugins would be removed.",
+                    console::style("[=]").bold().dim().green(),
+                    pruned.len(),
+                );
+                return Ok(());
+            }
+            if !pruned.is_empty() && !confirm_removal(pruned.len(), matches.get_flag("yes"))? {
+                return Ok(());
+            }
+
+            for plugin in pruned.iter() {
+                remove_local_plugin(plugin).await?;
+            }
+            println!(
+                "{} Plugins pruned, removed {} plugins.",
+                console::style("[=]").bold().dim().green(),
+                pruned.len(),
+            );
+            Ok(())
+        }
+        Some(("info", matches)) => {
+            let plugin_uri = matches.get_one::<String>("plugin_uri").unwrap();
+            plugin_info(plugin_uri).await
+        }
+        Some(("repair", _)) => {
+            let repaired = repair_plugins().await?;
+            println!(
+                "{} Plugins repaired, recreated {} .meta files.",
+                console::style("[=]").bold().dim().green(),
+                repaired,
+            );
+            Ok(())
+        }
+        Some(("verify", matches)) => {
+            let filter = VerifyFilter {
+                plugin_name: matches.get_one::<String>("plugin_name").map(String::as_str),
+                prefix: matches.get_one::<String>("prefix").map(String::as_str),
+            };
+            let failed = verify_plugins(filter).await?;
+            if failed.is_empty() {
+                println!(
+                    "{} All plugins verified successfully.",
+                    console::style("[=]").bold().dim().green(),
+                );
+                Ok(())
+            } else if matches.get_flag("fix") {
+                // only the plugins that were verified above are removed
+                print_orphaned_plugins(&failed);
+                if !confirm_removal(failed.len(), matches.get_flag("yes"))? {
+                    return Ok(());
+                }
+
+                for (path, reason) in failed.iter() {
+                    remove_orphaned_plugin(path, reason).await?;
+                }
+                println!(
+                    "{} Plugins fixed, removed {} plugins.",
+                    console::style("[=]").bold().dim().green(),
+                    failed.len(),
+                );
+                Ok(())
+            } else {
+                println!(
+                    "{} {} plugins failed the verification, run `memflowup plugins verify --fix` to remove them.",
+                    console::style("[X]").bold().dim().red(),
+                    failed.len(),
+                );
+                Err(Error::Signature(format!(
+                    "{} plugins failed the verification",
+                    failed.len()
+                )))
+            }
+        }
+        Some(("which", matches)) => {
+            // only the path is printed so the output can be used in scripts
+            let plugin_uri = matches.get_one::<String>("plugin_uri").unwrap();
+            let plugin = util::find_local_plugin(plugin_uri, None).await?;
+            println!("{}", plugin.plugin_file_name.display());
+            Ok(())
         }
         Some(("remove", matches)) => {
             let plugin_uris = matches
@@ -41,20 +300,124 @@ pub async fn handle(matches: &ArgMatches) -> Result<()> {
                 .unwrap_or_default()
                 .cloned()
                 .collect::<Vec<_>>();
+            let prefix = matches.get_one::<String>("prefix").map(String::as_str);
+
+            // without any uris all plugins with the given prefix are removed
+            let plugins = if plugin_uris.is_empty() {
+                match prefix {
+                    Some(prefix) => local_plugins_by_prefix(prefix).await?,
+                    None => Vec::new(),
+                }
+            } else {
+                let mut plugins: Vec<LocalPlugin> = Vec::new();
+                for plugin_uri in plugin_uris.iter() {
+                    let plugin = find_local_plugin_by_uri(plugin_uri, prefix).await?;
+                    if !plugins
+                        .iter()
+                        .any(|p| p.plugin_file_name == plugin.plugin_file_name)
+                    {
+                        plugins.push(plugin);
+                    }
+                }
+                plugins
+            };
 
-            for plugin_uri in plugin_uris.iter() {
-                remove_local_plugin_by_uri(plugin_uri).await?;
+            if plugins.is_empty() {
+                println!(
+                    "{} No plugins to remove found.",
+                    console::style("[-]").bold().dim(),
+                );
+                return Ok(());
+            }
+
+            for plugin in plugins.iter() {
+                println!(
+                    "{} Would delete plugin: {:?}",
+                    console::style("[-]").bold().dim(),
+                    plugin.plugin_file_name.as_os_str(),
+                );
+            }
+            if !confirm_removal(plugins.len(), matches.get_flag("yes"))? {
+                return Ok(());
+            }
+
+            for plugin in plugins.iter() {
+                remove_local_plugin(plugin).await?;
+            }
+
+            if plugin_uris.is_empty() {
+                if let Some(prefix) = prefix {
+                    println!(
+                        "{} Removed {} plugins with prefix `{}`.",
+                        console::style("[=]").bold().dim().green(),
+                        plugins.len(),
+                        prefix,
+                    );
+                }
             }
 
             Ok(())
         }
-        Some(("clean", _)) => {
-            let orphaned = remove_orphaned_plugins().await?;
-            let old_versions = remove_old_plugin_versions().await?;
+        Some(("update", matches)) => super::update::handle(matches).await,
+        Some(("clean", matches)) => {
+            let keep = *matches.get_one::<usize>("keep").unwrap();
+
+            // orphaned plugins are removed first, so they do not count as a version to keep
+            let orphaned = orphaned_plugins().await?;
+            let plugins = util::local_plugins()
+                .await?
+                .into_iter()
+                .filter(|plugin| {
+                    !orphaned
+                        .iter()
+                        .any(|(path, _)| *path == plugin.plugin_file_name)
+                })
+                .collect::<Vec<_>>();
+            let old_versions = old_plugin_versions(&plugins, keep);
+
+            if orphaned.is_empty() && old_versions.is_empty() {
+                println!(
+                    "{} Plugins cleaned, removed 0 plugins.",
+                    console::style("[=]").bold().dim().green(),
+                );
+                return Ok(());
+            }
+
+            print_orphaned_plugins(&orphaned);
+            for (plugin, newest_version) in old_versions.iter() {
+                let reason = match newest_version {
+                    Some(version) => format!("superseded by newer version {}", version),
+                    None => "no versions are kept".to_owned(),
+                };
+                println!(
+                    "{} Would delete plugin: {:?} ({})",
+                    console::style("[-]").bold().dim(),
+                    plugin.plugin_file_name.as_os_str(),
+                    reason
+                );
+            }
+            if matches.get_flag("dry-run") {
+                println!(
+                    "{} Dry run, {} plugins would be removed.",
+                    console::style("[=]").bold().dim().green(),
+                    orphaned.len() + old_versions.len(),
+                );
+                return Ok(());
+            }
+            if !confirm_removal(orphaned.len() + old_versions.len(), matches.get_flag("yes"))? {
+                return Ok(());
+            }
+
+            for (path, reason) in orphaned.iter() {
+                remove_orphaned_plugin(path, reason).await?;
+            }
+            for (plugin, _) in old_versions.iter() {
+                remove_local_plugin(plugin).await?;
+            }
             println!(
                 "{} Plugins cleaned, removed {} plugins.",
                 console::style("[=]").bold().dim().green(),
-                orphaned + old_versions,
+                orphaned.len() + old_versions.len(),
             );
             Ok(())
         }
@@ -62,18 +425,110 @@ pub async fn handle(matches: &ArgMatches) -> Result<()> {
     }
 }
 
-async fn list_local_plugins(plugin_name: Option<&str>) -> Result<()> {
-    let plugins = util::local_plugins().await?;
-    for plugin in plugins.into_iter() {
+/// Filters applied to the local plugins in `plugins list`
+#[derive(Clone, Copy)]
+struct ListFilter<'a> {
+    plugin_name: Option<&'a str>,
+    prefix: Option<&'a str>,
+    installed_via: Option<&'a [InstallSource]>,
+    architecture: Option<&'a str>,
+    file_type: Option<&'a str>,
+}
+
+impl ListFilter<'_> {
+    fn matches(&self, plugin: &LocalPlugin) -> bool {
         // optionally filter by plugin name
-        if let Some(plugin_name) = plugin_name {
+        if let Some(plugin_name) = self.plugin_name {
             if plugin.descriptor.name != plugin_name {
-                continue;
+                return false;
+            }
+        }
+
+        // optionally filter by file name prefix
+        if self.prefix.is_some() && plugin.prefix.as_deref() != self.prefix {
+            return false;
+        }
+
+        // optionally filter by installation source
+        if let Some(installed_via) = self.installed_via {
+            match plugin.installed_via {
+                Some(source) if installed_via.contains(&source) => (),
+                _ => return false,
+            }
+        }
+
+        // optionally filter by platform, the values are the lowercase names used by the registry
+        if let Some(architecture) = self.architecture {
+            if format!("{:?}", plugin.descriptor.architecture).to_ascii_lowercase() != architecture
+            {
+                return false;
+            }
+        }
+        if let Some(file_type) = self.file_type {
+            if format!("{:?}", plugin.descriptor.file_type).to_ascii_lowercase() != file_type {
+                return false;
             }
         }
 
+        true
+    }
+}
+
+async fn list_local_plugins(filter: ListFilter<'_>, long: bool, json: bool) -> Result<()> {
+    let mut incompatible_plugins = 0;
+    let mut listed_plugins = Vec::new();
+    let plugins = util::local_plugins().await?;
+    for plugin in plugins.into_iter().filter(|plugin| filter.matches(plugin)) {
+        if json {
+            if plugin.descriptor.plugin_version != MEMFLOW_PLUGIN_VERSION {
+                incompatible_plugins += 1;
+            }
+            listed_plugins.push(plugin);
+            continue;
+        }
+
+        let installed_via = plugin
+            .installed_via
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| "unknown".to_owned());
+
+        // plugins built against a different plugin abi will be rejected by memflow when loading them
+        let incompatible = if plugin.descriptor.plugin_version != MEMFLOW_PLUGIN_VERSION {
+            incompatible_plugins += 1;
+            console::style(format!(
+                "  INCOMPATIBLE (abi {}, expected {})",
+                plugin.descriptor.plugin_version, MEMFLOW_PLUGIN_VERSION
+            ))
+            .bold()
+            .yellow()
+            .to_string()
+        } else {
+            String::new()
+        };
+
+        if long {
+            println!(
+                "{0: <16} {1: <16} {2: <12} {3: <4} {4: <8} {5: <65} {6: <19} {7: <13} {8:}{9}",
+                plugin.descriptor.name,
+                plugin.descriptor.version,
+                format!(
+                    "{:?}/{:?}",
+                    plugin.descriptor.file_type, plugin.descriptor.architecture
+                )
+                .to_ascii_lowercase(),
+                plugin.descriptor.plugin_version,
+                &plugin.digest[..7],
+                plugin.digest,
+                plugin.created_at,
+                installed_via,
+                plugin.descriptor.description,
+                incompatible,
+            );
+            continue;
+        }
+
         println!(
-            "{0: <16} {1: <16} {2: <12} {3: <4} {4: <8} {5: <65} {6:}",
+            "{0: <16} {1: <16} {2: <12} {3: <4} {4: <8} {5: <65} {6: <19} {7:}{8}",
             plugin.descriptor.name,
             plugin.descriptor.version,
             format!(
@@ -85,14 +540,243 @@ async fn list_local_plugins(plugin_name: Option<&str>) -> Result<()> {
             &plugin.digest[..7],
             plugin.digest,
             plugin.created_at,
+            installed_via,
+            incompatible,
+        );
+    }
+
+    if json {
+        super::print_json(&listed_plugins)?;
+    }
+
+    if incompatible_plugins > 0 {
+        // printed to stderr so the listing itself stays parseable
+        eprintln!(
+            "{} {} installed plugins were built against a different plugin abi than memflowup (abi {}) and cannot be loaded, run `memflowup plugins update` to update them.",
+            console::style("[-]").bold().dim().yellow(),
+            incompatible_plugins,
+            MEMFLOW_PLUGIN_VERSION,
+        );
+    }
+    Ok(())
+}
+
+/// A locally installed plugin for which a newer variant is available in the registry
+#[derive(Serialize)]
+struct OutdatedPlugin {
+    name: String,
+    arch: String,
+    installed_version: String,
+    installed_digest: String,
+    available_version: String,
+    available_digest: String,
+    plugin_file_name: PathBuf,
+}
+
+/// Lists all plugins whose newest installed variant differs from the latest variant in the registry.
+///
+/// Plugins are compared per file type, architecture and prefix so plugins pulled with `--arch all` are compared with the matching variant.
+async fn list_outdated_plugins(
+    filter: ListFilter<'_>,
+    registry: Option<&str>,
+    include_prerelease: bool,
+    json: bool,
+) -> Result<()> {
+    // local plugins are sorted newest first, so the first plugin of each group is the newest installed one
+    let mut seen = HashSet::new();
+    let mut plugins = Vec::new();
+    for plugin in util::local_plugins().await?.into_iter() {
+        if !filter.matches(&plugin) {
+            continue;
+        }
+        if matches!(
+            plugin.installed_via,
+            Some(InstallSource::Source | InstallSource::Local)
+        ) {
+            if !json {
+                println!(
+                    "{} Skipping {} {}, it was built from source",
+                    console::style("[-]").bold().dim(),
+                    plugin.descriptor.name,
+                    plugin.descriptor.version,
+                );
+            }
+            continue;
+        }
+        if seen.insert((
+            plugin.descriptor.name.clone(),
+            plugin.descriptor.file_type,
+            plugin.descriptor.architecture,
+            plugin.prefix.clone(),
+        )) {
+            plugins.push(plugin);
+        }
+    }
+
+    let concurrency = std::thread::available_parallelism()
+        .map(NonZeroUsize::get)
+        .unwrap_or(1);
+    let results = futures_util::stream::iter(plugins.iter().map(|plugin| async move {
+        let file_type = format!("{:?}", plugin.descriptor.file_type).to_ascii_lowercase();
+        let architecture = format!("{:?}", plugin.descriptor.architecture).to_ascii_lowercase();
+        let platform = registry::PlatformFilter {
+            file_type: Some(file_type.as_str()).filter(|f| registry::FILE_TYPES.contains(f)),
+            architecture: Some(architecture.as_str())
+                .filter(|a| registry::ARCHITECTURES.contains(a)),
+        };
+        let variants =
+            registry::plugin_versions(registry, &plugin.descriptor.name, false, platform, 25)
+                .await?;
+        Ok::<_, Error>(registry::latest_variant(variants, include_prerelease))
+    }))
+    .buffered(concurrency)
+    .collect::<Vec<_>>()
+    .await;
+
+    let mut outdated = Vec::new();
+    for (plugin, result) in plugins.iter().zip(results) {
+        match result {
+            Ok(Some(variant)) if variant.digest != plugin.digest => {
+                outdated.push(outdated_plugin(plugin, &variant))
+            }
+            Ok(Some(_)) => (),
+            Ok(None) | Err(Error::NotFound(_)) => {
+                if !json {
+                    println!(
+                        "{} Skipping {} {}, it was not found in the registry",
+                        console::style("[-]").bold().dim(),
+                        plugin.descriptor.name,
+                        plugin.descriptor.version,
+                    );
+                }
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    if json {
+        return super::print_json(&outdated);
+    }
+
+    println!(
+        "{0: <16} {1: <12} {2: <16} {3: <8} {4: <16} DIGEST",
+        "NAME", "ARCH", "INSTALLED", "DIGEST", "AVAILABLE"
+    );
+    for plugin in outdated.iter() {
+        println!(
+            "{0: <16} {1: <12} {2: <16} {3: <8} {4: <16} {5:}",
+            plugin.name,
+            plugin.arch,
+            plugin.installed_version,
+            &plugin.installed_digest[..7],
+            plugin.available_version,
+            &plugin.available_digest[..7],
         );
     }
+
     Ok(())
 }
 
-async fn remove_local_plugin_by_uri(plugin_uri_str: &str) -> Result<()> {
-    match util::find_local_plugin(plugin_uri_str).await {
-        Ok(plugin) => remove_local_plugin(&plugin).await,
+fn outdated_plugin(plugin: &LocalPlugin, variant: &PluginVariant) -> OutdatedPlugin {
+    OutdatedPlugin {
+        name: plugin.descriptor.name.clone(),
+        arch: format!(
+            "{:?}/{:?}",
+            plugin.descriptor.file_type, plugin.descriptor.architecture
+        )
+        .to_ascii_lowercase(),
+        installed_version: plugin.descriptor.version.clone(),
+        installed_digest: plugin.digest.clone(),
+        available_version: variant.descriptor.version.clone(),
+        available_digest: variant.digest.clone(),
+        plugin_file_name: plugin.plugin_file_name.clone(),
+    }
+}
+
+async fn plugin_info(plugin_uri_str: &str) -> Result<()> {
+    let plugin = match util::find_local_plugin(plugin_uri_str, None).await {
+        Ok(plugin) => plugin,
+        Err(err) => {
+            println!(
+                "{} Plugin `{}` not found",
+                console::style("[X]").bold().dim().red(),
+                plugin_uri_str
+            );
+            return Err(err);
+        }
+    };
+
+    // a single plugin binary can contain multiple plugins, all of them are listed in the .meta file
+    let descriptors = util::read_plugin_meta(&plugin.meta_file_name)
+        .map(|metadata| metadata.metadata.descriptors)
+        .unwrap_or_else(|_| vec![plugin.descriptor.clone()]);
+    let file_size = tokio::fs::metadata(&plugin.plugin_file_name)
+        .await
+        .map(|m| format!("{} bytes", m.len()))
+        .unwrap_or_else(|_| "file not found".to_owned());
+
+    println!("{0: <24} {1}", "Path:", plugin.plugin_file_name.display());
+    println!(
+        "{0: <24} {1}",
+        "Meta path:",
+        plugin.meta_file_name.display()
+    );
+    println!("{0: <24} {1}", "Size:", file_size);
+    println!("{0: <24} {1}", "Digest:", plugin.digest);
+    println!("{0: <24} {1}", "Created:", plugin.created_at);
+    if let Some(prefix) = &plugin.prefix {
+        println!("{0: <24} {1}", "Prefix:", prefix);
+    }
+    println!(
+        "{0: <24} {1}",
+        "Installed via:",
+        plugin
+            .installed_via
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| "unknown".to_owned())
+    );
+    println!(
+        "{0: <24} {1}",
+        "Installed by memflowup:",
+        plugin
+            .installed_by_memflowup
+            .as_deref()
+            .unwrap_or("unknown")
+    );
+    println!(
+        "{0: <24} {1}",
+        "Installed with ABI:",
+        plugin
+            .memflow_plugin_version
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| "unknown".to_owned())
+    );
+
+    for descriptor in descriptors.iter() {
+        println!();
+        println!("{0: <24} {1}", "Name:", descriptor.name);
+        println!("{0: <24} {1}", "Version:", descriptor.version);
+        println!("{0: <24} {1:?}", "Kind:", descriptor.plugin_kind);
+        println!("{0: <24} {1}", "Export name:", descriptor.export_name);
+        println!("{0: <24} {1}", "ABI:", descriptor.plugin_version);
+        println!(
+            "{0: <24} {1}",
+            "Architecture:",
+            format!("{:?}/{:?}", descriptor.file_type, descriptor.architecture)
+                .to_ascii_lowercase()
+        );
+        println!("{0: <24} {1}", "Description:", descriptor.description);
+    }
+
+    Ok(())
+}
+
+async fn find_local_plugin_by_uri(
+    plugin_uri_str: &str,
+    prefix: Option<&str>,
+) -> Result<LocalPlugin> {
+    match util::find_local_plugin(plugin_uri_str, prefix).await {
+        Ok(plugin) => Ok(plugin),
         Err(err) => {
             println!(
                 "{} Plugin `{}` not found",
@@ -104,6 +788,48 @@ async fn remove_local_plugin_by_uri(plugin_uri_str: &str) -> Result<()> {
     }
 }
 
+/// Returns all plugins that were installed with the given file name prefix.
+async fn local_plugins_by_prefix(prefix: &str) -> Result<Vec<LocalPlugin>> {
+    Ok(util::local_plugins()
+        .await?
+        .into_iter()
+        .filter(|plugin| plugin.prefix.as_deref() == Some(prefix))
+        .collect())
+}
+
+/// Asks the user to confirm the deletion of the plugins printed before.
+///
+/// If stdin is not a terminal the prompt cannot be answered, so `--yes` is required instead.
+fn confirm_removal(count: usize, yes: bool) -> Result<bool> {
+    if yes {
+        return Ok(true);
+    }
+
+    if !std::io::stdin().is_terminal() {
+        println!(
+            "{} Refusing to delete plugins without confirmation, use `--yes` to delete them non-interactively",
+            console::style("[X]").bold().dim().red(),
+        );
+        return Err(Error::Unknown(
+            "plugin removal requires confirmation".to_owned(),
+        ));
+    }
+
+    let ans = Confirm::new(&format!("Do you want to delete {} plugins?", count))
+        .with_default(false)
+        .prompt();
+    match ans {
+        Ok(true) => Ok(true),
+        _ => {
+            println!(
+                "{} Aborted, no plugins were deleted.",
+                console::style("[-]").bold().dim(),
+            );
+            Ok(false)
+        }
+    }
+}
+
 async fn remove_local_plugin(local_plugin: &LocalPlugin) -> Result<()> {
     // delete plugin binary
     if let Err(err) = tokio::fs::remove_file(&local_plugin.plugin_file_name).await {
@@ -144,103 +870,479 @@ async fn remove_local_plugin(local_plugin: &LocalPlugin) -> Result<()> {
     Ok(())
 }
 
-/// Removes all plugins which do not have a proper .meta file associated with them.
-async fn remove_orphaned_plugins() -> Result<usize> {
-    let mut orphaned_plugins = 0;
+/// Prints the verification status of all plugins (optionally filtered by name) without modifying them.
+/// Returns the amount of plugins that failed the verification.
+async fn verify_plugins(filter: VerifyFilter<'_>) -> Result<Vec<(PathBuf, OrphanReason)>> {
+    let checked = filter.apply(check_plugins().await?);
+
+    println!("{0: <13} FILE", "STATUS");
+    for (path, orphaned) in checked.iter() {
+        // the status is padded before styling it, otherwise the escape codes would break the alignment
+        let status = match orphaned {
+            None => console::style(format!("{: <13}", "OK")).green(),
+            Some(reason) => console::style(format!("{: <13}", reason.status())).red(),
+        };
+        println!("{} {}", status, path.display());
+    }
+
+    Ok(checked
+        .into_iter()
+        .filter_map(|(path, orphaned)| orphaned.map(|reason| (path, reason)))
+        .collect())
+}
+
+/// Filters applied to the plugin files in `plugins verify`
+#[derive(Clone, Copy, Default)]
+struct VerifyFilter<'a> {
+    plugin_name: Option<&'a str>,
+    prefix: Option<&'a str>,
+}
+
+impl VerifyFilter<'_> {
+    /// Returns the checked plugin files matching this filter.
+    fn apply(
+        &self,
+        checked: Vec<(PathBuf, Option<OrphanReason>)>,
+    ) -> Vec<(PathBuf, Option<OrphanReason>)> {
+        checked
+            .into_iter()
+            .filter(|(path, _)| self.matches(path))
+            .collect()
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        if self.plugin_name.is_none() && self.prefix.is_none() {
+            return true;
+        }
+
+        match util::read_plugin_meta(path.with_extension("meta")) {
+            Ok(meta) => {
+                let matches_name = self
+                    .plugin_name
+                    .map(|plugin_name| {
+                        meta.metadata
+                            .descriptors
+                            .iter()
+                            .any(|descriptor| descriptor.name == plugin_name)
+                    })
+                    .unwrap_or(true);
+                matches_name && (self.prefix.is_none() || meta.prefix.as_deref() == self.prefix)
+            }
+            // plugins without a readable .meta file can only be matched by their file name
+            Err(_) => {
+                let file_name = path
+                    .file_name()
+                    .map(|file_name| file_name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let matches_name = self
+                    .plugin_name
+                    .map(|plugin_name| file_name.contains(&format!("_{}_", plugin_name)))
+                    .unwrap_or(true);
+                let matches_prefix = self
+                    .prefix
+                    .map(|prefix| file_name.starts_with(&format!("{}_", prefix)))
+                    .unwrap_or(true);
+                matches_name && matches_prefix
+            }
+        }
+    }
+}
+
+/// Returns all plugins which do not have a proper .meta file associated with them.
+async fn orphaned_plugins() -> Result<Vec<(PathBuf, OrphanReason)>> {
+    Ok(check_plugins()
+        .await?
+        .into_iter()
+        .filter_map(|(path, orphaned)| orphaned.map(|reason| (path, reason)))
+        .collect())
+}
+
+/// Prints the orphaned plugins that are about to be removed together with the reason.
+fn print_orphaned_plugins(orphaned: &[(PathBuf, OrphanReason)]) {
+    for (path, reason) in orphaned.iter() {
+        println!(
+            "{} Would delete orphaned plugin: {:?} ({})",
+            console::style("[-]").bold().dim(),
+            path.as_os_str(),
+            reason
+        );
+    }
+}
+
+async fn remove_orphaned_plugin(path: &Path, reason: &OrphanReason) -> Result<()> {
+    // TODO: try parse metafile and check digest to be triple sure
+
+    // remove plugin
+    if let Err(err) = tokio::fs::remove_file(&path).await {
+        println!(
+            "{} Unable to delete plugin {:?}: {}",
+            console::style("[X]").bold().dim().red(),
+            path.file_name().unwrap_or_default().to_os_string(),
+            err
+        );
+        return Err(err.into());
+    }
+
+    // try to remove meta file (this is allowed to fail)
+    let mut meta_file_name = path.to_path_buf();
+    meta_file_name.set_extension("meta");
+    if meta_file_name.exists() {
+        // only try to delete the file if it exists, so we do not print an error in all cases
+        if let Err(err) = tokio::fs::remove_file(meta_file_name).await {
+            println!(
+                "{} Unable to delete .meta file for plugin {:?}: {}",
+                console::style("[X]").bold().dim().red(),
+                path.file_name().unwrap_or_default().to_os_string(),
+                err
+            );
+        }
+    }
+
+    println!(
+        "{} Deleted orphaned plugin: {:?} ({})",
+        console::style("[=]").bold().dim().green(),
+        path.as_os_str(),
+        reason
+    );
+
+    Ok(())
+}
 
+/// Checks all plugin files in the plugins directory against their .meta files.
+/// Returns the path of each plugin (sorted) together with the reason why it is considered orphaned.
+async fn check_plugins() -> Result<Vec<(PathBuf, Option<OrphanReason>)>> {
+    // collect all plugin files first so they can be checked in parallel
+    let mut plugin_paths = Vec::new();
     let paths = std::fs::read_dir(util::plugins_path())?;
     for path in paths.filter_map(|p| p.ok()) {
-        if let Some(extension) = path.path().extension() {
-            // TODO: should we only check for plugin_extension here?
-            if extension.to_str().unwrap_or_default() == memflow::plugins::plugin_extension() {
-                // check if the corresponding .meta file exists
-                let mut meta_file_name = path.path();
-                meta_file_name.set_extension("meta");
-
-                let orphaned = if meta_file_name.exists() {
-                    if let Ok(metadata) = serde_json::from_str::<PluginMetadata>(
-                        &tokio::fs::read_to_string(meta_file_name).await?,
-                    ) {
-                        let bytes = tokio::fs::read(path.path()).await?;
-                        let digest = sha256::digest(&bytes[..]);
-                        if metadata.digest == digest {
-                            None
-                        } else {
-                            // digest in .meta is not matching file on disk
-                            Some("checksum mismatch in .meta file")
-                        }
-                    } else {
-                        // invalid .meta file
-                        Some("corrupted .meta file")
-                    }
-                } else {
-                    // .meta file does not exist
-                    Some(".meta file missing")
-                };
+        // plugins for other targets (see `pull --arch all`) are checked as well
+        if util::is_plugin_file(&path.path()) {
+            plugin_paths.push(path.path());
+        }
+    }
+    plugin_paths.sort();
 
-                if let Some(reason) = orphaned {
-                    // TODO: try parse metafile and check digest to be triple sure
-
-                    // remove plugin
-                    if let Err(err) = tokio::fs::remove_file(path.path()).await {
-                        println!(
-                            "{} Unable to delete plugin {:?}: {}",
-                            console::style("[X]").bold().dim().red(),
-                            path.path().file_name().unwrap_or_default().to_os_string(),
-                            err
-                        );
-                        return Err(err.into());
-                    }
+    // hashing is cpu bound, so the checks are spread across a bounded set of blocking tasks.
+    // `buffered` ensures the results are returned in the same order as the input.
+    let concurrency = std::thread::available_parallelism()
+        .map(NonZeroUsize::get)
+        .unwrap_or(1);
+    let results = futures_util::stream::iter(plugin_paths.into_iter().map(|path| async move {
+        let check_path = path.clone();
+        let reason = tokio::task::spawn_blocking(move || orphan_reason(&check_path))
+            .await
+            .map_err(|err| Error::Unknown(err.to_string()))??;
+        Ok::<_, Error>((path, reason))
+    }))
+    .buffered(concurrency)
+    .collect::<Vec<_>>()
+    .await;
 
-                    // try to remove meta file (this is allowed to fail)
-                    let mut meta_file_name = path.path();
-                    meta_file_name.set_extension("meta");
-                    if meta_file_name.exists() {
-                        // only try to delete the file if it exists, so we do not print an error in all cases
-                        if let Err(err) = tokio::fs::remove_file(meta_file_name).await {
-                            println!(
-                                "{} Unable to delete .meta file for plugin {:?}: {}",
-                                console::style("[X]").bold().dim().red(),
-                                path.path().file_name().unwrap_or_default().to_os_string(),
-                                err
-                            );
-                        }
-                    }
+    results.into_iter().collect()
+}
 
-                    println!(
-                        "{} Deleted orphaned plugin: {:?} ({})",
-                        console::style("[=]").bold().dim().green(),
-                        path.path().as_os_str(),
-                        reason
-                    );
+/// Reason why a plugin is considered orphaned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OrphanReason {
+    ChecksumMismatch,
+    MetaCorrupted,
+    MetaMissing,
+}
 
-                    orphaned_plugins += 1;
-                    continue;
-                }
+impl OrphanReason {
+    /// Short status shown by `plugins verify`
+    fn status(&self) -> &'static str {
+        match self {
+            OrphanReason::ChecksumMismatch => "MISMATCH",
+            OrphanReason::MetaCorrupted => "META-CORRUPT",
+            OrphanReason::MetaMissing => "META-MISSING",
+        }
+    }
+}
+
+impl fmt::Display for OrphanReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrphanReason::ChecksumMismatch => write!(f, "checksum mismatch in .meta file"),
+            OrphanReason::MetaCorrupted => write!(f, "corrupted .meta file"),
+            OrphanReason::MetaMissing => write!(f, ".meta file missing"),
+        }
+    }
+}
+
+/// Checks if the plugin at the given path has a valid .meta file with a matching digest.
+/// Returns the reason why the plugin is considered orphaned or `None` if the plugin is valid.
+fn orphan_reason(plugin_path: &Path) -> Result<Option<OrphanReason>> {
+    // check if the corresponding .meta file exists
+    let mut meta_file_name = plugin_path.to_path_buf();
+    meta_file_name.set_extension("meta");
+
+    let orphaned = if meta_file_name.exists() {
+        if let Ok(LocalPluginMetadata { metadata, .. }) = util::read_plugin_meta(meta_file_name) {
+            if metadata.digest == util::sha256_file(plugin_path)? {
+                None
+            } else {
+                // digest in .meta is not matching file on disk
+                Some(OrphanReason::ChecksumMismatch)
+            }
+        } else {
+            // invalid .meta file
+            Some(OrphanReason::MetaCorrupted)
+        }
+    } else {
+        // .meta file does not exist
+        Some(OrphanReason::MetaMissing)
+    };
+
+    Ok(orphaned)
+}
+
+/// Recreates missing or unreadable .meta files from the plugin binaries.
+/// Returns the amount of .meta files that have been written.
+async fn repair_plugins() -> Result<usize> {
+    let mut repaired = 0;
+
+    let mut plugin_paths = std::fs::read_dir(util::plugins_path())?
+        .filter_map(|p| p.ok())
+        .map(|p| p.path())
+        .filter(|path| util::is_plugin_file(path))
+        .collect::<Vec<_>>();
+    plugin_paths.sort();
+
+    for plugin_path in plugin_paths.iter() {
+        let mut meta_file_name = plugin_path.clone();
+        meta_file_name.set_extension("meta");
+        if util::read_plugin_meta(&meta_file_name).is_ok() {
+            continue;
+        }
+
+        match repair_plugin_meta(plugin_path, &meta_file_name).await {
+            Ok(()) => {
+                println!(
+                    "{} Recreated plugin metadata: {:?}",
+                    console::style("[=]").bold().dim().green(),
+                    meta_file_name
+                );
+                repaired += 1;
+            }
+            Err(err) => {
+                println!(
+                    "{} Unable to repair plugin {:?}: {}",
+                    console::style("[X]").bold().dim().red(),
+                    plugin_path,
+                    err
+                );
             }
         }
     }
 
-    Ok(orphaned_plugins)
+    Ok(repaired)
 }
 
-/// Removes all plugins which do not have a proper .meta file associated with them.
-async fn remove_old_plugin_versions() -> Result<usize> {
-    let mut old_plugin_versions = 0;
+/// Derives the .meta file by parsing the descriptors of the plugin binary and recomputing its digest.
+async fn repair_plugin_meta(plugin_path: &Path, meta_file_name: &Path) -> Result<()> {
+    let bytes = tokio::fs::read(plugin_path).await?;
+    let descriptors = plugin_analyzer::parse_descriptors(&bytes)?;
+    if descriptors.is_empty() {
+        return Err(Error::NotFound(
+            "no plugin descriptors found in binary".to_owned(),
+        ));
+    }
 
-    // get a list of pre-sorted plugins, we simply need to delete all but the first occurence of each plugin
-    let mut seen = HashSet::new();
-    let plugins = util::local_plugins().await?;
+    let modified: DateTime<Utc> = tokio::fs::metadata(plugin_path).await?.modified()?.into();
+    let metadata = LocalPluginMetadata {
+        metadata: PluginMetadata {
+            digest: sha256::digest(&bytes[..]),
+            // the original signature cannot be recovered
+            signature: String::new(),
+            created_at: modified.naive_utc().trunc_subsecs(0),
+            descriptors,
+        },
+        installed_via: None,
+        installed_by_memflowup: None,
+        memflow_plugin_version: None,
+        prefix: None,
+    };
+    util::write_file_atomic(
+        meta_file_name,
+        serde_json::to_string_pretty(&metadata)?.as_bytes(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Returns all but the `keep` most recent versions of each plugin.
+/// Each plugin is returned together with the newest version that is kept (if any).
+fn old_plugin_versions(plugins: &[LocalPlugin], keep: usize) -> Vec<(LocalPlugin, Option<String>)> {
+    let mut old_plugin_versions = Vec::new();
+
+    // the list is pre-sorted, we simply need to return all but the first `keep` occurences of each plugin
+    let mut seen = HashMap::new();
     for plugin in plugins.iter() {
-        if seen.contains(&plugin.descriptor.name) {
-            // delete the file if we have seen a newer version already
-            remove_local_plugin(plugin).await?;
-            old_plugin_versions += 1;
+        // plugins installed with different prefixes or for different targets are treated as separate plugins
+        let (count, newest_version) = seen.entry(version_group(plugin)).or_insert((0, None));
+        if *count >= keep {
+            // enough newer versions have been seen already
+            old_plugin_versions.push((plugin.clone(), newest_version.clone()));
         } else {
-            // add the file to our "seen" list
-            seen.insert(plugin.descriptor.name.clone());
+            // count the file as a version we keep
+            if *count == 0 {
+                *newest_version = Some(plugin.descriptor.version.clone());
+            }
+            *count += 1;
+        }
+    }
+
+    old_plugin_versions
+}
+
+/// Returns the key under which the versions of a plugin are grouped when removing old versions.
+fn version_group(
+    plugin: &LocalPlugin,
+) -> (Option<String>, String, PluginFileType, PluginArchitecture) {
+    (
+        plugin.prefix.clone(),
+        plugin.descriptor.name.clone(),
+        plugin.descriptor.file_type,
+        plugin.descriptor.architecture,
+    )
+}
+
+/// Removes all but the `keep` most recent versions of the plugins matching the filter.
+///
+/// The removal has to be confirmed unless `yes` is set (see `confirm_removal`), returns the amount of removed plugins.
+pub(super) async fn remove_old_plugin_versions<F: Fn(&LocalPlugin) -> bool>(
+    keep: usize,
+    filter: F,
+    yes: bool,
+) -> Result<usize> {
+    let plugins = util::local_plugins()
+        .await?
+        .into_iter()
+        .filter(filter)
+        .collect::<Vec<_>>();
+    let old_plugin_versions = old_plugin_versions(&plugins, keep);
+    if old_plugin_versions.is_empty() {
+        return Ok(0);
+    }
+
+    for (plugin, _) in old_plugin_versions.iter() {
+        println!(
+            "{} Would delete plugin: {:?}",
+            console::style("[-]").bold().dim(),
+            plugin.plugin_file_name.as_os_str(),
+        );
+    }
+    if !confirm_removal(old_plugin_versions.len(), yes)? {
+        return Ok(0);
+    }
+
+    for (plugin, _) in old_plugin_versions.iter() {
+        remove_local_plugin(plugin).await?;
+    }
+
+    Ok(old_plugin_versions.len())
+}
+
+/// Returns all plugin versions that were created before `older_than` (relative to now).
+/// The newest version of each plugin is always kept.
+fn plugins_older_than(plugins: &[LocalPlugin], older_than: Duration) -> Result<Vec<LocalPlugin>> {
+    let older_than = chrono::Duration::from_std(older_than)
+        .map_err(|_| Error::Parse("duration is out of range".to_owned()))?;
+    let threshold = Utc::now().naive_utc() - older_than;
+
+    // the list is pre-sorted, the first occurence of each plugin is its newest version
+    let mut seen = HashSet::new();
+    let mut pruned = Vec::new();
+    for plugin in plugins.iter() {
+        // plugins installed with different prefixes or for different targets are treated as separate plugins
+        if seen.insert(version_group(plugin)) {
+            continue;
+        }
+
+        if plugin.created_at < threshold {
+            pruned.push(plugin.clone());
         }
     }
 
-    Ok(old_plugin_versions)
+    Ok(pruned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a plugin binary with a .meta file whose digest does not match the binary.
+    fn write_mismatched_plugin(dir: &Path, name: &str) -> PathBuf {
+        let path = dir.join(format!("libmemflow_{}_0.1.0_abcdef0.so", name));
+        std::fs::write(&path, name).unwrap();
+        let meta = serde_json::json!({
+            "digest": "0".repeat(64),
+            "signature": "",
+            "created_at": "2024-01-01T00:00:00",
+            "descriptors": [{
+                "plugin_kind": "connector",
+                "export_name": "MEMFLOW_CONNECTOR",
+                "file_type": "elf",
+                "architecture": "x86_64",
+                "plugin_version": 1,
+                "name": name,
+                "version": "0.1.0",
+                "description": "",
+            }],
+        });
+        std::fs::write(path.with_extension("meta"), meta.to_string()).unwrap();
+        path
+    }
+
+    #[test]
+    fn verify_filter_excludes_other_failing_plugins() {
+        let dir = std::env::temp_dir().join(format!(
+            "memflowup-test-verify-filter-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let selected = write_mismatched_plugin(&dir, "coredump");
+        let other = write_mismatched_plugin(&dir, "qemu");
+        let orphan = dir.join("libmemflow_orphan_0.1.0_abcdef0.so");
+        std::fs::write(&orphan, "orphan").unwrap();
+        let checked = vec![
+            (selected.clone(), Some(OrphanReason::ChecksumMismatch)),
+            (other, Some(OrphanReason::ChecksumMismatch)),
+            (orphan, Some(OrphanReason::MetaMissing)),
+        ];
+
+        let filter = VerifyFilter {
+            plugin_name: Some("coredump"),
+            prefix: None,
+        };
+        let filtered = filter.apply(checked.clone());
+        assert_eq!(
+            filtered,
+            vec![(selected, Some(OrphanReason::ChecksumMismatch))]
+        );
+
+        // without a filter all failing plugins are selected
+        assert_eq!(VerifyFilter::default().apply(checked.clone()), checked);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn clean_rejects_keeping_zero_versions() {
+        for flag in ["--keep", "--max-versions"] {
+            let err = metadata()
+                .try_get_matches_from(["plugins", "clean", flag, "0"])
+                .unwrap_err();
+            assert_eq!(err.kind(), clap::error::ErrorKind::ValueValidation);
+        }
+
+        let matches = metadata()
+            .try_get_matches_from(["plugins", "clean", "--keep", "2"])
+            .unwrap();
+        let (_, matches) = matches.subcommand().unwrap();
+        assert_eq!(matches.get_one::<usize>("keep"), Some(&2));
+    }
 }
@@ -1,24 +1,40 @@
 //! Clap subcommand to pull plugins from a registry
 
-use std::path::Path;
+use std::{
+    collections::HashMap,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use clap::{Arg, ArgAction, ArgMatches, Command};
-use tokio::{fs::File, io::AsyncWriteExt};
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use futures_util::StreamExt;
+use indicatif::ProgressBar;
+use inquire::MultiSelect;
+use log::info;
 
 use crate::{
+    api,
     error::{Error, Result},
-    util,
+    events::{self, Event},
+    lockfile::{LockedPlugin, Lockfile, LOCKFILE_NAME},
+    registry, util,
 };
 use memflow_registry::{
-    PluginUri, SignatureVerifier, MEMFLOW_DEFAULT_REGISTRY, MEMFLOW_DEFAULT_REGISTRY_VERIFYING_KEY,
+    storage::PluginMetadata, PluginUri, PluginVariant, SignatureVerifier, MEMFLOW_DEFAULT_REGISTRY,
+    MEMFLOW_DEFAULT_REGISTRY_VERIFYING_KEY,
 };
 
-use super::config::read_config;
+use super::config::{read_config, Config};
 
 #[inline]
 pub fn metadata() -> Command {
     Command::new("pull").args([
         Arg::new("plugin_uri").action(ArgAction::Append),
+        Arg::new("from-file")
+            .long("from-file")
+            .help("reads additional plugin uris from a file (one uri per line, lines starting with # are ignored)")
+            .action(ArgAction::Set),
         Arg::new("all")
             .short('a')
             .long("all")
@@ -39,16 +55,59 @@ pub fn metadata() -> Command {
             .long("pub-key")
             .help("public key used to verify the binary signature (this is required for self-hosted registries)")
             .action(ArgAction::Set),
+        Arg::new("include-prerelease")
+            .long("include-prerelease")
+            .alias("pre")
+            .help("includes prerelease versions when resolving the latest version")
+            .action(ArgAction::SetTrue),
+        Arg::new("arch")
+            .long("arch")
+            .help("architecture of the plugin to pull (`all` pulls every architecture and os variant available in the registry)")
+            .value_parser(["host", "all"])
+            .default_value("host")
+            .action(ArgAction::Set),
+        Arg::new("prefix")
+            .long("prefix")
+            .help("prepends a prefix to the installed file names (e.g. to keep separate plugin sets per project)")
+            .value_parser(util::parse_prefix)
+            .action(ArgAction::Set),
+        Arg::new("jobs")
+            .short('j')
+            .long("jobs")
+            .value_parser(value_parser!(usize))
+            .default_value("4")
+            .help("the amount of plugins to download concurrently")
+            .action(ArgAction::Set),
+        Arg::new("fail-fast")
+            .long("fail-fast")
+            .help("stops at the first failure instead of continuing with the remaining plugins")
+            .action(ArgAction::SetTrue),
+        Arg::new("locked")
+            .long("locked")
+            .help("pulls the exact digests recorded in `memflowup.lock` in the current directory and records newly resolved plugins in it")
+            .conflicts_with("arch")
+            .action(ArgAction::SetTrue),
+        Arg::new("dry-run")
+            .long("dry-run")
+            .help("resolves the plugins and prints where they would be written without downloading or writing anything")
+            .action(ArgAction::SetTrue),
+        Arg::new("insecure-skip-verify")
+            .long("insecure-skip-verify")
+            .help("skips the signature verification of downloaded plugins (DANGEROUS: only use this for testing a self-hosted registry)")
+            .action(ArgAction::SetTrue),
         ])
 }
 
 pub async fn handle(matches: &ArgMatches) -> Result<()> {
     let config = read_config().await?;
-    let plugin_uris = matches
+    let mut plugin_uris = matches
         .get_many::<String>("plugin_uri")
         .unwrap_or_default()
         .cloned()
         .collect::<Vec<_>>();
+    if let Some(from_file) = matches.get_one::<String>("from-file") {
+        plugin_uris.extend(util::read_plugin_uris_file(from_file).await?);
+    }
     let all = matches.get_flag("all");
     let force = matches.get_flag("force");
     let registry = matches
@@ -59,78 +118,292 @@ pub async fn handle(matches: &ArgMatches) -> Result<()> {
         .get_one::<String>("pub-key")
         .map(Path::new)
         .or(config.pub_key_file.as_deref());
+    let include_prerelease =
+        matches.get_flag("include-prerelease") || config.include_prerelease.unwrap_or_default();
+    let all_archs = matches.get_one::<String>("arch").map(String::as_str) == Some("all");
+    let insecure_skip_verify = matches.get_flag("insecure-skip-verify");
+    let fail_fast = matches.get_flag("fail-fast");
+    let jobs = *matches.get_one::<usize>("jobs").unwrap();
+    let prefix = matches.get_one::<String>("prefix").map(String::as_str);
+    let dry_run = matches.get_flag("dry-run");
+    let mut lockfile = if matches.get_flag("locked") {
+        Some(Lockfile::read(LOCKFILE_NAME).await?)
+    } else {
+        None
+    };
+
+    let verifier = if insecure_skip_verify {
+        None
+    } else {
+        Some(signature_verifier(pub_key_file, &config)?)
+    };
+
+    // interactively select plugins if no uri was given
+    if !all && plugin_uris.is_empty() && !matches.contains_id("from-file") {
+        if !std::io::stdin().is_terminal() || events::is_json() {
+            println!(
+                "{} No plugin uri given. Either provide the plugins to pull or use `--all`",
+                console::style("[X]").bold().dim().red(),
+            );
+            return Err(Error::NotFound("no plugin uri given".to_owned()));
+        }
+        plugin_uris = select_plugins(registry).await?;
+    }
+
+    let mut options = PullOptions {
+        registry,
+        force,
+        verifier: verifier.as_ref(),
+        include_prerelease,
+        all_archs,
+        prefix,
+        installed: HashMap::new(),
+        lockfile: lockfile.as_ref(),
+        dry_run,
+        versioned_file_names: config.versioned_file_names.unwrap_or(true),
+    };
 
-    // TODO: support custom registry for wildcard
+    // all plugins are resolved against the same registry they are listed from
     if all {
-        let plugins = memflow_registry::client::plugins(None).await?;
-        for plugin in plugins.iter() {
-            if let Err(err) = pull(registry, &plugin.name, force, pub_key_file).await {
-                println!(
-                    "{} Error downloading plugin {:?}: {}",
-                    console::style("[X]").bold().dim().red(),
-                    plugin.name,
-                    err
-                );
+        let spinner = util::multi_progress().add(ProgressBar::new_spinner());
+        spinner.set_message("fetching plugin list...");
+        spinner.enable_steady_tick(Duration::from_millis(100));
+        let plugins = registry::plugins(registry).await;
+        spinner.finish_and_clear();
+        let plugins = plugins?;
+
+        if !events::is_json() {
+            println!(
+                "{} Found {} plugins, downloading...",
+                console::style("[-]").bold().dim(),
+                plugins.len()
+            );
+        }
+
+        // plugins that are already installed are skipped right after resolving their latest variant
+        // without querying their metadata or downloading them again
+        if !force {
+            options.installed = util::local_plugins()
+                .await?
+                .into_iter()
+                .filter(|plugin| plugin.prefix.as_deref() == prefix)
+                .filter(|plugin| plugin.plugin_file_name.exists())
+                .map(|plugin| (plugin.digest, plugin.plugin_file_name))
+                .collect();
+        }
+
+        plugin_uris = plugins.into_iter().map(|plugin| plugin.name).collect();
+    }
+
+    // pull plugins concurrently, a failing plugin does not abort the others unless --fail-fast is set
+    let options = &options;
+    let mut pulls = futures_util::stream::iter(plugin_uris.iter())
+        .map(|plugin_uri| async move { (plugin_uri, pull(plugin_uri, options).await) })
+        .buffer_unordered(jobs.max(1));
+    let mut locked_plugins = Vec::new();
+    while let Some((plugin_uri, result)) = pulls.next().await {
+        match result {
+            Ok(locked) => locked_plugins.extend(locked),
+            Err(err) => {
+                events::emit(Event::Error {
+                    plugin: plugin_uri,
+                    message: err.to_string(),
+                });
+                if fail_fast {
+                    // dropping the stream cancels all pending downloads
+                    return Err(err);
+                }
             }
         }
-    } else {
-        // TODO: parallel downloads
-        for plugin_uri in plugin_uris.iter() {
-            if let Err(err) = pull(registry, plugin_uri, force, pub_key_file).await {
-                println!(
-                    "{} Error downloading plugin {:?}: {}",
-                    console::style("[X]").bold().dim().red(),
-                    plugin_uri,
-                    err
-                );
+    }
+    drop(pulls);
+
+    // only successfully pulled plugins are recorded in the lockfile
+    if let Some(lockfile) = lockfile.as_mut() {
+        if !locked_plugins.is_empty() && !dry_run {
+            for (plugin_name, locked) in locked_plugins.into_iter() {
+                lockfile.insert(&plugin_name, locked);
             }
+            lockfile.write(LOCKFILE_NAME).await?;
         }
     }
 
     Ok(())
 }
 
-async fn pull(
-    registry: Option<&str>,
-    plugin_uri: &str,
-    force: bool,
-    pub_key: Option<&Path>,
-) -> Result<()> {
-    // load the signature verifier
-    let verifier = if let Some(pub_key) = pub_key {
+/// Loads the signature verifier from the custom public key, the configured default public key or the bundled public key.
+pub(super) fn signature_verifier(
+    pub_key_file: Option<&Path>,
+    config: &Config,
+) -> Result<SignatureVerifier> {
+    if let Some(pub_key_file) = pub_key_file {
         // load custom public key
-        SignatureVerifier::new(pub_key)
+        Ok(SignatureVerifier::new(pub_key_file)?)
+    } else if let Some(default_pub_key) = &config.default_pub_key {
+        // use the configured default public key
+        Ok(SignatureVerifier::with_str(default_pub_key)?)
     } else {
         // use default bundled public key
-        SignatureVerifier::with_str(MEMFLOW_DEFAULT_REGISTRY_VERIFYING_KEY)
-    }?;
+        Ok(SignatureVerifier::with_str(
+            MEMFLOW_DEFAULT_REGISTRY_VERIFYING_KEY,
+        )?)
+    }
+}
+
+/// Fetches the plugin list from the registry and lets the user select the plugins to pull.
+async fn select_plugins(registry: Option<&str>) -> Result<Vec<String>> {
+    let spinner = ProgressBar::new_spinner();
+    spinner.set_message("fetching plugin list...");
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    let plugins = registry::plugins(registry).await;
+    spinner.finish_and_clear();
+    let plugins = plugins?;
+
+    let options = plugins
+        .iter()
+        .map(|plugin| format!("{0: <16} {1}", plugin.name, plugin.description))
+        .collect::<Vec<_>>();
+    let selected = MultiSelect::new("Select the plugins to pull:", options)
+        .raw_prompt()
+        .map_err(|err| Error::Unknown(err.to_string()))?;
+
+    Ok(selected
+        .into_iter()
+        .map(|option| plugins[option.index].name.clone())
+        .collect())
+}
+
+/// Options shared by all plugins pulled in a single invocation
+pub(super) struct PullOptions<'a> {
+    pub registry: Option<&'a str>,
+    pub force: bool,
+    pub verifier: Option<&'a SignatureVerifier>,
+    pub include_prerelease: bool,
+    pub all_archs: bool,
+    pub prefix: Option<&'a str>,
+    /// Installed plugin files by digest, variants contained in here are skipped
+    pub installed: HashMap<String, PathBuf>,
+    /// Lockfile with the pinned digests when `--locked` is set
+    pub lockfile: Option<&'a Lockfile>,
+    /// Only prints the plugins that would be written
+    pub dry_run: bool,
+    /// Includes the plugin version in the installed file names
+    pub versioned_file_names: bool,
+}
 
-    // find the correct plugin variant based on the input arguments
+/// Pulls the plugin and returns the lockfile entry of the pulled variant when `--locked` is set.
+pub(super) async fn pull(
+    plugin_uri: &str,
+    options: &PullOptions<'_>,
+) -> Result<Option<(String, LockedPlugin)>> {
+    // find the correct plugin variant(s) based on the input arguments
+    let (plugin_uri, digest) = registry::split_digest(plugin_uri)?;
     let plugin_uri = PluginUri::with_defaults(
         plugin_uri,
-        registry.unwrap_or(MEMFLOW_DEFAULT_REGISTRY),
+        options.registry.unwrap_or(MEMFLOW_DEFAULT_REGISTRY),
         "latest",
     )?;
-    let variant = memflow_registry::client::find_by_uri(&plugin_uri, false, None).await?;
+    info!(
+        "pulling plugin `{}` from registry {}",
+        plugin_uri.image(),
+        registry::registry_url(Some(plugin_uri.registry()))?
+    );
+    // entries for another registry are ignored and replaced with the newly resolved variant
+    let locked = options
+        .lockfile
+        .and_then(|lockfile| lockfile.get(plugin_uri.image()))
+        .filter(|locked| locked.registry == plugin_uri.registry());
+    let variants = if let Some(digest) = digest {
+        // content addressed pulls always take precedence over the lockfile
+        vec![registry::find_by_digest(&plugin_uri, digest).await?]
+    } else if let Some(locked) = locked {
+        info!(
+            "using locked version {} ({}) of plugin `{}`",
+            locked.version,
+            locked.digest,
+            plugin_uri.image()
+        );
+        vec![
+            registry::find_by_version_and_digest(&plugin_uri, &locked.version, &locked.digest)
+                .await?,
+        ]
+    } else if options.all_archs {
+        registry::find_all_archs_by_uri(&plugin_uri, None, options.include_prerelease).await?
+    } else {
+        vec![registry::find_by_uri(&plugin_uri, false, None, options.include_prerelease).await?]
+    };
+
+    for variant in variants.iter() {
+        if let Some(file_name) = options.installed.get(&variant.digest) {
+            events::emit(Event::Skipped {
+                plugin: plugin_uri.image(),
+                digest: &variant.digest,
+                path: file_name,
+                reason: "is already installed",
+            });
+            continue;
+        }
+
+        pull_variant(&plugin_uri, variant, options).await?;
+    }
+
+    // `--locked` cannot be combined with `--arch all`, so there is exactly one variant here
+    Ok(options.lockfile.and(variants.first()).map(|variant| {
+        (
+            plugin_uri.image().to_owned(),
+            LockedPlugin {
+                registry: plugin_uri.registry().to_owned(),
+                version: variant.descriptor.version.clone(),
+                digest: variant.digest.clone(),
+            },
+        )
+    }))
+}
 
+async fn pull_variant(
+    plugin_uri: &PluginUri,
+    variant: &PluginVariant,
+    options: &PullOptions<'_>,
+) -> Result<()> {
     // query file metadata for variant
-    let metadata = memflow_registry::client::metadata(&plugin_uri, &variant).await?;
+    let metadata = registry::metadata(plugin_uri, variant).await?;
 
     // check if file already exists
-    let file_name = util::plugin_file_name(&metadata);
-    if !force && file_name.exists() {
-        let bytes = tokio::fs::read(&file_name).await?;
-        let digest = sha256::digest(&bytes[..]);
+    // when pulling all architectures the file name contains the architecture so variants can coexist
+    let file_name = if options.all_archs {
+        util::plugin_file_name_with_arch(&metadata, options.prefix, options.versioned_file_names)
+    } else {
+        util::plugin_file_name(&metadata, options.prefix, options.versioned_file_names)
+    };
+    if !options.force && file_name.exists() {
+        // cheap check via the local .meta file and the file size reported by the registry
+        if is_up_to_date(plugin_uri, variant, &file_name).await {
+            events::emit(Event::Skipped {
+                plugin: plugin_uri.image(),
+                digest: &variant.digest,
+                path: &file_name,
+                reason: "is already up to date",
+            });
+            return Ok(());
+        }
+
+        let digest = {
+            let file_name = file_name.clone();
+            tokio::task::spawn_blocking(move || util::sha256_file(file_name))
+                .await
+                .map_err(|err| Error::Unknown(err.to_string()))??
+        };
 
         // check if the plugin digest matches with the one from memflow-registry
         if variant.digest == digest {
-            println!(
-                "{} Plugin {:?} already exists with the same checksum, skipping download.",
-                console::style("[-]").bold().dim().yellow(),
-                file_name.file_name().unwrap()
-            );
+            events::emit(Event::Skipped {
+                plugin: plugin_uri.image(),
+                digest: &variant.digest,
+                path: &file_name,
+                reason: "already exists with the same checksum",
+            });
             return Ok(());
-        } else {
+        } else if !events::is_json() {
             println!(
                 "{} Plugin {:?} already exists with a different checksum, redownloading.",
                 console::style("[-]").bold().dim().yellow(),
@@ -139,44 +412,99 @@ async fn pull(
         }
     }
 
-    // query file and download to memory
-    let response = memflow_registry::client::download(&plugin_uri, &variant).await?;
-    let buffer = util::read_response_with_progress(response).await?;
+    if options.dry_run {
+        // only the size is queried, the binary itself is not downloaded
+        let bytes = registry::download_size(plugin_uri, variant)
+            .await
+            .ok()
+            .flatten();
+        events::emit(Event::Planned {
+            plugin: plugin_uri.image(),
+            version: &variant.descriptor.version,
+            digest: &variant.digest,
+            path: &file_name,
+            bytes,
+        });
+        return Ok(());
+    }
+
+    // download into a .part file next to the plugin so an interrupted download can be resumed
+    let mut plugin = match api::download(plugin_uri, variant, metadata, &file_name).await {
+        Ok(plugin) => plugin,
+        Err(err @ Error::Signature(_)) => {
+            println!(
+                "{} Plugin checksum does not match the registry",
+                console::style("[X]").bold().dim().red(),
+            );
+            return Err(err);
+        }
+        Err(err) => return Err(err),
+    };
 
     // verify file signature
-    if verifier
-        .is_valid(buffer.as_ref(), &variant.signature)
-        .is_err()
-    {
+    if let Err(err) = plugin.verify(options.verifier).await {
         println!(
             "{} Plugin signature verification failed (in case you're using a self-hosted registry, please provide a custom public key)",
             console::style("[X]").bold().dim().red(),
         );
-        return Err(Error::Signature("plugin verification failed".to_owned()));
+        return Err(err);
+    }
+    if plugin.verified {
+        events::emit(Event::Verified {
+            plugin: plugin_uri.image(),
+            digest: &variant.digest,
+        });
+    } else {
+        eprintln!(
+            "{} {}",
+            console::style("[!]").bold().red(),
+            console::style("WARNING: Signature verification was skipped, the plugin binary is UNVERIFIED and might have been tampered with!").bold().red(),
+        );
     }
 
-    // write file (signature matches)
-    let mut file = File::create(&file_name).await?;
-    file.write_all(buffer.as_ref()).await?;
-    file.flush().await?;
-
-    println!(
-        "{} Wrote plugin to: {:?}",
-        console::style("[=]").bold().dim().green(),
-        file_name.as_os_str(),
-    );
+    // write file (signature matches or verification was explicitly skipped) and its .meta file
+    let file_name = api::install(plugin, options.prefix).await?;
 
-    // store .meta file of plugin containing all relevant information
-    // TODO: this does not contain all plugins in this file - allow querying that from memflow-registry as well
-    let mut file_name = file_name.clone();
-    file_name.set_extension("meta");
-    tokio::fs::write(&file_name, serde_json::to_string_pretty(&metadata)?).await?;
+    events::emit(Event::Installed {
+        plugin: plugin_uri.image(),
+        digest: &variant.digest,
+        path: &file_name,
+        verified: options.verifier.is_some(),
+    });
 
-    println!(
-        "{} Wrote plugin metadata to: {:?}",
-        console::style("[=]").bold().dim().green(),
-        file_name.as_os_str(),
-    );
+    if !events::is_json() {
+        println!(
+            "{} Wrote plugin metadata to: {:?}",
+            console::style("[=]").bold().dim().green(),
+            api::meta_file_name(&file_name).as_os_str(),
+        );
+    }
 
     Ok(())
 }
+
+/// Checks if the installed plugin matches the registry variant without downloading or hashing it.
+///
+/// The plugin is considered up to date if the digest in its .meta file matches the variant
+/// and the file size on disk matches the size reported by the registry.
+async fn is_up_to_date(plugin_uri: &PluginUri, variant: &PluginVariant, file_name: &Path) -> bool {
+    let mut meta_file_name = file_name.to_path_buf();
+    meta_file_name.set_extension("meta");
+    let meta_digest = tokio::fs::read_to_string(&meta_file_name)
+        .await
+        .ok()
+        .and_then(|content| serde_json::from_str::<PluginMetadata>(&content).ok())
+        .map(|metadata| metadata.digest);
+    if meta_digest.as_deref() != Some(variant.digest.as_str()) {
+        return false;
+    }
+
+    let local_size = match tokio::fs::metadata(file_name).await {
+        Ok(m) => m.len(),
+        Err(_) => return false,
+    };
+    matches!(
+        registry::download_size(plugin_uri, variant).await,
+        Ok(Some(size)) if size == local_size
+    )
+}
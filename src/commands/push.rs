@@ -1,13 +1,20 @@
 //! Clap subcommand to push plugins in a registry
 
-use std::{path::Path, process::exit};
+use std::{
+    path::{Path, PathBuf},
+    process::{exit, Stdio},
+};
 
-use clap::{Arg, ArgAction, ArgMatches, Command};
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use futures_util::StreamExt;
+use memflow::plugins::plugin_analyzer;
 use memflow_registry::{rest::models::PluginUploadResponse, SignatureGenerator};
+use tokio::{io::AsyncWriteExt, process::Command as ProcessCommand};
 
 use crate::{
     error::{Error, Result},
-    util,
+    registry,
+    util::{self, InstallSource},
 };
 
 use super::config::read_config;
@@ -18,8 +25,27 @@ pub fn metadata() -> Command {
     Command::new("push").args([
         Arg::new("plugin_uris_or_files")
             .help("list of plugin uris or filenames")
-            .required(true)
+            .required_unless_present_any(["from-file", "dir"])
             .action(ArgAction::Append),
+        Arg::new("from-file")
+            .long("from-file")
+            .help("reads additional plugin uris or filenames from a file (one entry per line, lines starting with # are ignored)")
+            .action(ArgAction::Set),
+        Arg::new("dir")
+            .long("dir")
+            .help("uploads all plugin binaries found in the given directory")
+            .action(ArgAction::Set),
+        Arg::new("jobs")
+            .short('j')
+            .long("jobs")
+            .value_parser(value_parser!(usize))
+            .default_value("4")
+            .help("the amount of plugins to upload concurrently")
+            .action(ArgAction::Set),
+        Arg::new("fail-fast")
+            .long("fail-fast")
+            .help("stops at the first failure instead of continuing with the remaining plugins")
+            .action(ArgAction::SetTrue),
         Arg::new("file")
             .short('f')
             .long("file")
@@ -38,58 +64,67 @@ pub fn metadata() -> Command {
         Arg::new("priv-key")
             .short('p')
             .long("priv-key")
-            .help("private key used to sign the binary")
+            .help("private key used to sign the binary (takes precedence over the configured `signer_command`)")
             .action(ArgAction::Set),
     ])
 }
 
 pub async fn handle(matches: &ArgMatches) -> Result<()> {
     let config = read_config().await?;
-    let plugin_uris_or_files = matches
+    let mut plugin_uris_or_files = matches
         .get_many::<String>("plugin_uris_or_files")
         .unwrap_or_default()
         .cloned()
         .collect::<Vec<_>>();
     let file = matches.get_flag("file");
+    if let Some(from_file) = matches.get_one::<String>("from-file") {
+        if file {
+            plugin_uris_or_files.extend(util::read_list_file(from_file).await?);
+        } else {
+            plugin_uris_or_files.extend(util::read_plugin_uris_file(from_file).await?);
+        }
+    }
     let registry = matches
         .get_one::<String>("registry")
         .map(String::as_str)
         .or(config.registry.as_deref());
     let token = matches.get_one::<String>("token").or(config.token.as_ref());
-    let priv_key_file = matches
-        .get_one::<String>("priv-key")
-        .map(Path::new)
-        .or(config.priv_key_file.as_deref());
-    let priv_key_file = match priv_key_file {
-        Some(v) => v,
-        None => {
+    println!(
+        "{} Using registry {}",
+        console::style("[-]").bold().dim(),
+        registry::registry_url(registry)?
+    );
+    let signer = match (
+        matches.get_one::<String>("priv-key").map(Path::new),
+        config.signer_command,
+        config.priv_key_file.as_deref(),
+    ) {
+        (Some(priv_key_file), _, _) => Signer::PrivateKey(SignatureGenerator::new(priv_key_file)?),
+        (None, Some(signer_command), _) => Signer::Command(signer_command),
+        (None, None, Some(priv_key_file)) => {
+            Signer::PrivateKey(SignatureGenerator::new(priv_key_file)?)
+        }
+        (None, None, None) => {
             println!(
-                "{} Private key file is required for signing. Either configure it via `memflowup config` or the `--priv-key` argument",
+                "{} Private key file or signer command is required for signing. Either configure it via `memflowup config` or the `--priv-key` argument",
                 console::style("[X]").bold().dim().red(),
             );
             return Err(Error::NotFound("private key file not found".to_owned()));
         }
     };
 
-    let mut exit_code = 0;
+    // an explicitly given key always signs again, e.g. after the key has been rotated
+    let reuse_signatures = !matches.contains_id("priv-key");
+
+    let jobs = *matches.get_one::<usize>("jobs").unwrap();
+    let fail_fast = matches.get_flag("fail-fast");
 
+    let mut file_names = Vec::new();
     if !file {
         // try to find the plugin first, then upload it to the registry
         for plugin_uri in plugin_uris_or_files.iter() {
-            match util::find_local_plugin(plugin_uri).await {
-                Ok(plugin) => {
-                    if upload_plugin_file(
-                        registry,
-                        token.map(String::as_str),
-                        priv_key_file,
-                        &plugin.plugin_file_name,
-                    )
-                    .await
-                    .is_err()
-                    {
-                        exit_code = 1;
-                    }
-                }
+            match util::find_local_plugin(plugin_uri, None).await {
+                Ok(plugin) => file_names.push(plugin.plugin_file_name),
                 Err(err) => {
                     println!(
                         "{} Plugin `{}` not found",
@@ -101,40 +136,106 @@ pub async fn handle(matches: &ArgMatches) -> Result<()> {
             }
         }
     } else {
-        for file_name in plugin_uris_or_files.iter() {
-            // upload a file directly
-            if upload_plugin_file(
+        // upload files directly
+        file_names.extend(plugin_uris_or_files.iter().map(PathBuf::from));
+    }
+    if let Some(dir) = matches.get_one::<String>("dir") {
+        file_names.extend(plugin_files_in_dir(Path::new(dir)).await?);
+    }
+
+    let mut uploads = futures_util::stream::iter(file_names.iter())
+        .map(|file_name| {
+            upload_plugin_file(
                 registry,
                 token.map(String::as_str),
-                priv_key_file,
+                &signer,
+                reuse_signatures,
                 file_name,
             )
-            .await
-            .is_err()
-            {
-                exit_code = 1;
+        })
+        .buffer_unordered(jobs.max(1));
+    let mut failed = false;
+    while let Some(result) = uploads.next().await {
+        if let Err(err) = result {
+            if fail_fast {
+                // dropping the stream cancels all pending uploads
+                return Err(err);
             }
+            failed = true;
         }
     }
 
-    if exit_code == 0 {
+    if !failed {
         Ok(())
     } else {
-        exit(exit_code)
+        exit(1)
+    }
+}
+
+/// Returns all valid plugin binaries in the given directory.
+/// Files which are not memflow plugins are skipped with a warning.
+async fn plugin_files_in_dir(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    let mut file_names = Vec::new();
+    for path in paths.into_iter() {
+        let is_plugin_extension = path
+            .extension()
+            .map(|ext| ext == memflow::plugins::plugin_extension())
+            .unwrap_or_default();
+        let is_plugin = is_plugin_extension
+            && tokio::fs::read(&path)
+                .await
+                .ok()
+                .and_then(|bytes| plugin_analyzer::parse_descriptors(&bytes).ok())
+                .map(|descriptors| !descriptors.is_empty())
+                .unwrap_or_default();
+
+        if is_plugin {
+            file_names.push(path);
+        } else {
+            println!(
+                "{} Skipping {:?}, it is not a memflow plugin",
+                console::style("[-]").bold().dim().yellow(),
+                path
+            );
+        }
     }
+
+    Ok(file_names)
 }
 
 async fn upload_plugin_file<P: AsRef<Path>>(
     registry: Option<&str>,
     token: Option<&str>,
-    priv_key_file: &Path,
+    signer: &Signer,
+    reuse_signatures: bool,
     file_name: P,
 ) -> Result<()> {
     // TODO: upload progress
-    let mut generator = SignatureGenerator::new(priv_key_file)?;
-    match memflow_registry::client::upload(registry, token, file_name.as_ref(), &mut generator)
-        .await
-    {
+    let result = async {
+        let file_content = tokio::fs::read(file_name.as_ref()).await?;
+        let stored_signature = reuse_signatures
+            .then(|| stored_signature(file_name.as_ref(), &file_content))
+            .flatten();
+        let signature = match stored_signature {
+            Some(signature) => signature,
+            None => signer.sign(&file_content).await?,
+        };
+        let upload_file_name = file_name
+            .as_ref()
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_default();
+        registry::upload(registry, token, &upload_file_name, file_content, signature).await
+    }
+    .await;
+    match result {
         Ok(PluginUploadResponse::Added) => {
             println!(
                 "{} Uploaded plugin {:?}",
@@ -158,7 +259,80 @@ async fn upload_plugin_file<P: AsRef<Path>>(
                 file_name.as_ref(),
                 msg
             );
-            Err(Error::Http(msg.to_string()))
+            Err(msg)
+        }
+    }
+}
+
+/// Returns the signature that was stored in the .meta file when the plugin was built and signed locally.
+///
+/// Signatures of plugins pulled from a registry are not reused as they were created with the key of that registry.
+fn stored_signature(file_name: &Path, file_content: &[u8]) -> Option<String> {
+    let metadata = util::read_plugin_meta(file_name.with_extension("meta")).ok()?;
+    let built_locally = matches!(
+        metadata.installed_via,
+        Some(InstallSource::Source | InstallSource::Local)
+    );
+    if built_locally
+        && !metadata.metadata.signature.is_empty()
+        && metadata.metadata.digest == sha256::digest(file_content)
+    {
+        Some(metadata.metadata.signature)
+    } else {
+        None
+    }
+}
+
+/// Signs plugin binaries before they are uploaded.
+enum Signer {
+    /// Signs with a private key stored on disk
+    PrivateKey(SignatureGenerator),
+    /// Pipes the sha256 digest of the binary to an external command (e.g. for hardware tokens or remote signers)
+    /// and reads the hex encoded DER signature from its stdout
+    Command(String),
+}
+
+impl Signer {
+    async fn sign(&self, bytes: &[u8]) -> Result<String> {
+        match self {
+            Signer::PrivateKey(generator) => Ok(generator.clone().sign(bytes)?),
+            Signer::Command(command) => {
+                let mut child = if cfg!(windows) {
+                    let mut cmd = ProcessCommand::new("cmd");
+                    cmd.arg("/C").arg(command);
+                    cmd
+                } else {
+                    let mut cmd = ProcessCommand::new("sh");
+                    cmd.arg("-c").arg(command);
+                    cmd
+                }
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::inherit())
+                .kill_on_drop(true)
+                .spawn()?;
+
+                let digest = sha256::digest(bytes);
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(format!("{}\n", digest).as_bytes()).await?;
+                }
+
+                let output = child.wait_with_output().await?;
+                if !output.status.success() {
+                    return Err(Error::Unknown(format!(
+                        "signer command exited with {}",
+                        output.status
+                    )));
+                }
+
+                let signature = std::str::from_utf8(&output.stdout)?.trim().to_owned();
+                if signature.is_empty() || !signature.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(Error::Parse(
+                        "signer command did not print a hex encoded signature".to_owned(),
+                    ));
+                }
+                Ok(signature)
+            }
         }
     }
 }
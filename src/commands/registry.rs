@@ -1,11 +1,22 @@
 //! Clap subcommand to query the registry
 
+use std::collections::{BTreeMap, HashMap};
+
 use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use log::info;
+use memflow_registry::PluginVariant;
 
-use crate::error::Result;
+use crate::{
+    error::{Error, Result},
+    registry::{self, TokenStatus},
+    util::{self, LocalPlugin},
+};
 
 use super::config::read_config;
 
+/// Maximum amount of variants per plugin that are taken into account for `registry stats`.
+const STATS_VARIANT_LIMIT: usize = 1000;
+
 #[inline]
 pub fn metadata() -> Command {
     Command::new("registry")
@@ -26,12 +37,35 @@ pub fn metadata() -> Command {
                     .alias("all-architectures")
                     .help("shows plugins regardless of the current architecture")
                     .action(ArgAction::SetTrue),
+                Arg::new("arch")
+                    .long("arch")
+                    .value_parser(registry::ARCHITECTURES)
+                    .help("only shows plugins for the given architecture instead of the current one")
+                    .action(ArgAction::Set),
+                Arg::new("file-type")
+                    .long("file-type")
+                    .value_parser(registry::FILE_TYPES)
+                    .help("only shows plugins for the given binary file type instead of the one of the current os")
+                    .action(ArgAction::Set),
                 Arg::new("limit")
                     .long("limit")
                     .value_parser(value_parser!(usize))
                     .default_value("25")
                     .help("the amount of plugins to show in the listing")
                     .action(ArgAction::Set),
+                Arg::new("all-versions")
+                    .long("all-versions")
+                    .help("shows every build of a version instead of only the newest one per version and architecture")
+                    .action(ArgAction::SetTrue),
+                Arg::new("sort")
+                    .long("sort")
+                    .value_parser(["name", "version", "created", "digest"])
+                    .help("sorts the listed versions by the given field instead of the registry order")
+                    .action(ArgAction::Set),
+                Arg::new("reverse")
+                    .long("reverse")
+                    .help("reverses the order of the listed versions")
+                    .action(ArgAction::SetTrue),
             ]),
             Command::new("remove").alias("rm").args([
                 Arg::new("plugin_digest")
@@ -44,12 +78,21 @@ pub fn metadata() -> Command {
                     .help("bearer token used in the upload request")
                     .action(ArgAction::Set),
             ]),
+            Command::new("stats"),
+            Command::new("whoami").args([Arg::new("token")
+                .short('t')
+                .long("token")
+                .help("bearer token to validate")
+                .action(ArgAction::Set)]),
+        ])
+        .args([
+            Arg::new("registry")
+                .short('r')
+                .long("registry")
+                .help("custom registry to use")
+                .action(ArgAction::Set),
+            super::output_arg(),
         ])
-        .args([Arg::new("registry")
-            .short('r')
-            .long("registry")
-            .help("custom registry to use")
-            .action(ArgAction::Set)])
 }
 
 pub async fn handle(matches: &ArgMatches) -> Result<()> {
@@ -58,28 +101,67 @@ pub async fn handle(matches: &ArgMatches) -> Result<()> {
         .get_one::<String>("registry")
         .map(String::as_str)
         .or(config.registry.as_deref());
+    info!("using registry {}", registry::registry_url(registry)?);
 
     // TODO: list local + remote plugins
     // TODO: allow changing to another registry provider
     match matches.subcommand() {
         Some(("list", matches)) => {
+            let json = super::is_json_output(matches);
             let all_archs = matches.get_flag("all-archs");
+            let filter = registry::PlatformFilter {
+                file_type: matches.get_one::<String>("file-type").map(String::as_str),
+                architecture: matches.get_one::<String>("arch").map(String::as_str),
+            };
+
+            let sort = matches.get_one::<String>("sort").map(String::as_str);
+            let reverse = matches.get_flag("reverse");
+            let all_versions = matches.get_flag("all-versions");
+
+            // locally installed plugins are used to mark installed versions in the listing
+            let local_plugins = util::local_plugins().await.unwrap_or_default();
 
             if let Some(plugin_name) = matches.get_one::<String>("plugin_name") {
                 let limit = matches.get_one::<usize>("limit").unwrap();
-                super::print_plugin_versions_header();
-                list_plugin_versions(registry, plugin_name, all_archs, *limit).await?;
+                let mut variants =
+                    registry::plugin_versions(registry, plugin_name, all_archs, filter, *limit)
+                        .await?;
+                if !all_versions {
+                    variants = dedup_variants(variants);
+                }
+                sort_variants(&mut variants, sort, reverse);
+                if json {
+                    super::print_json(&variants)?;
+                } else {
+                    super::print_plugin_versions_header();
+                    print_plugin_versions(&variants, &local_plugins);
+                }
             } else {
                 let versions = matches.get_flag("versions");
 
                 // list all plugins
-                let plugins = memflow_registry::client::plugins(registry).await?;
+                let plugins = registry::plugins(registry).await?;
                 if versions {
                     // TODO: display plugins that do not have a version for our current os?
-                    super::print_plugin_versions_header();
+                    let mut all_variants = Vec::new();
                     for plugin in plugins.iter() {
-                        list_plugin_versions(registry, &plugin.name, all_archs, 1).await?;
+                        let variants =
+                            registry::plugin_versions(registry, &plugin.name, all_archs, filter, 1)
+                                .await?;
+                        all_variants.extend(variants);
+                    }
+                    if !all_versions {
+                        all_variants = dedup_variants(all_variants);
                     }
+                    sort_variants(&mut all_variants, sort, reverse);
+                    if json {
+                        super::print_json(&all_variants)?;
+                    } else {
+                        super::print_plugin_versions_header();
+                        print_plugin_versions(&all_variants, &local_plugins);
+                    }
+                } else if json {
+                    super::print_json(&plugins)?;
                 } else {
                     println!("{0: <16} DESCRIPTION", "NAME");
                     for plugin in plugins.iter() {
@@ -95,9 +177,14 @@ pub async fn handle(matches: &ArgMatches) -> Result<()> {
             let plugin_digest = matches.get_one::<String>("plugin_digest").unwrap();
             let token = matches.get_one::<String>("token").or(config.token.as_ref());
 
+            println!(
+                "{} Removing plugin {} from registry {}",
+                console::style("[-]").bold().dim(),
+                plugin_digest,
+                registry::registry_url(registry)?
+            );
             if let Err(err) =
-                memflow_registry::client::delete(registry, token.map(String::as_str), plugin_digest)
-                    .await
+                registry::delete(registry, token.map(String::as_str), plugin_digest).await
             {
                 println!(
                     "{} Unable to delete plugin entry from registry: {}",
@@ -108,27 +195,167 @@ pub async fn handle(matches: &ArgMatches) -> Result<()> {
 
             Ok(())
         }
+        Some(("stats", _)) => registry_stats(registry).await,
+        Some(("whoami", matches)) => {
+            let token = match matches.get_one::<String>("token").or(config.token.as_ref()) {
+                Some(token) => token,
+                None => {
+                    println!(
+                        "{} No token configured. Either configure it via `memflowup config` or the `--token` argument",
+                        console::style("[X]").bold().dim().red(),
+                    );
+                    return Err(Error::NotFound("token not found".to_owned()));
+                }
+            };
+
+            let registry_url = registry::registry_url(registry)?;
+            match registry::check_token(registry, token).await? {
+                TokenStatus::Valid => {
+                    println!(
+                        "{} Token is valid for registry {} (push and remove access)",
+                        console::style("[=]").bold().dim().green(),
+                        registry_url,
+                    );
+                    Ok(())
+                }
+                TokenStatus::NotRequired => {
+                    println!(
+                        "{} Registry {} does not require a token, any token has push and remove access",
+                        console::style("[-]").bold().dim().yellow(),
+                        registry_url,
+                    );
+                    Ok(())
+                }
+                TokenStatus::Invalid => {
+                    println!(
+                        "{} Token is invalid or expired for registry {}",
+                        console::style("[X]").bold().dim().red(),
+                        registry_url,
+                    );
+                    Err(Error::Registry("token was rejected".to_owned()))
+                }
+                TokenStatus::Unknown(status) => {
+                    println!(
+                        "{} Unable to determine if the token is valid for registry {} (unexpected response: {})",
+                        console::style("[X]").bold().dim().red(),
+                        registry_url,
+                        status,
+                    );
+                    Err(Error::Registry(format!(
+                        "unexpected response while checking the token: {}",
+                        status
+                    )))
+                }
+            }
+        }
         _ => {
             unreachable!()
         }
     }
 }
 
-async fn list_plugin_versions(
-    registry: Option<&str>,
-    plugin_name: &str,
-    all_archs: bool,
-    limit: usize,
-) -> Result<()> {
-    // list versions of a specific plugin
-    let plugins =
-        memflow_registry::client::plugin_versions(registry, plugin_name, all_archs, None, limit)
-            .await?;
-    // TODO: dedup versions
-
-    for variant in plugins.iter() {
+/// Prints a summary of all plugins and variants in the registry.
+async fn registry_stats(registry: Option<&str>) -> Result<()> {
+    let plugins = registry::plugins(registry).await?;
+
+    let mut total_variants = 0;
+    let mut targets = BTreeMap::new();
+    let mut newest = None;
+    for plugin in plugins.iter() {
+        let variants = registry::plugin_versions(
+            registry,
+            &plugin.name,
+            true,
+            registry::PlatformFilter::default(),
+            STATS_VARIANT_LIMIT,
+        )
+        .await?;
+
+        total_variants += variants.len();
+        for variant in variants.iter() {
+            let target = format!(
+                "{:?}/{:?}",
+                variant.descriptor.file_type, variant.descriptor.architecture
+            )
+            .to_ascii_lowercase();
+            *targets.entry(target).or_insert(0) += 1;
+            newest = newest.max(Some(variant.created_at));
+        }
+    }
+
+    println!("{0: <16} {1}", "PLUGINS", plugins.len());
+    println!("{0: <16} {1}", "VARIANTS", total_variants);
+    println!(
+        "{0: <16} {1}",
+        "NEWEST",
+        newest
+            .map(|created_at| created_at.to_string())
+            .unwrap_or_else(|| "-".to_owned())
+    );
+    println!();
+    println!("{0: <16} VARIANTS", "ARCH");
+    for (target, count) in targets.iter() {
+        println!("{0: <16} {1}", target, count);
+    }
+
+    Ok(())
+}
+
+/// Collapses variants with the same plugin name, version and architecture into the newest one.
+///
+/// The registry stores a separate variant for every build of a version, the collapsed variant
+/// takes the position of the first variant of its group.
+fn dedup_variants(variants: Vec<PluginVariant>) -> Vec<PluginVariant> {
+    let mut result: Vec<PluginVariant> = Vec::with_capacity(variants.len());
+    let mut groups = HashMap::<_, usize>::new();
+    for variant in variants.into_iter() {
+        let key = (
+            variant.descriptor.name.clone(),
+            variant.descriptor.version.clone(),
+            variant.descriptor.file_type,
+            variant.descriptor.architecture,
+        );
+        match groups.get(&key) {
+            Some(&idx) => {
+                if variant.created_at > result[idx].created_at {
+                    result[idx] = variant;
+                }
+            }
+            None => {
+                groups.insert(key, result.len());
+                result.push(variant);
+            }
+        }
+    }
+    result
+}
+
+/// Sorts the variants by the given field, the registry order is kept for equal values.
+///
+/// Versions are compared as semver, versions which are not valid semver are sorted before all others.
+fn sort_variants(variants: &mut [PluginVariant], sort: Option<&str>, reverse: bool) {
+    match sort {
+        Some("name") => variants.sort_by(|a, b| a.descriptor.name.cmp(&b.descriptor.name)),
+        Some("version") => variants.sort_by_cached_key(|variant| {
+            (
+                semver::Version::parse(&variant.descriptor.version).ok(),
+                variant.descriptor.version.clone(),
+            )
+        }),
+        Some("created") => variants.sort_by_key(|variant| variant.created_at),
+        Some("digest") => variants.sort_by(|a, b| a.digest.cmp(&b.digest)),
+        _ => (),
+    }
+    if reverse {
+        variants.reverse();
+    }
+}
+
+fn print_plugin_versions(variants: &[PluginVariant], local_plugins: &[LocalPlugin]) {
+    for variant in variants.iter() {
+        let plugin_name = variant.descriptor.name.as_str();
         println!(
-            "{0: <16} {1: <16} {2: <12} {3: <4} {4: <8} {5: <65} {6:}",
+            "{0: <16} {1: <16} {2: <12} {3: <4} {4: <8} {5: <65} {6:}{7}",
             plugin_name,
             variant.descriptor.version,
             format!(
@@ -140,8 +367,26 @@ async fn list_plugin_versions(
             &variant.digest[..7],
             variant.digest,
             variant.created_at,
+            if is_installed(plugin_name, variant, local_plugins) {
+                " [installed]"
+            } else {
+                ""
+            },
         );
     }
+}
 
-    Ok(())
+/// Checks if the variant is installed locally.
+/// Variants are matched by their digest, in case no local plugin has the same digest
+/// a local plugin with the same name, version and architecture is considered a match as well.
+fn is_installed(plugin_name: &str, variant: &PluginVariant, local_plugins: &[LocalPlugin]) -> bool {
+    local_plugins
+        .iter()
+        .any(|local| local.digest == variant.digest)
+        || local_plugins.iter().any(|local| {
+            local.descriptor.name == plugin_name
+                && local.descriptor.version == variant.descriptor.version
+                && local.descriptor.file_type == variant.descriptor.file_type
+                && local.descriptor.architecture == variant.descriptor.architecture
+        })
 }
@@ -0,0 +1,137 @@
+//! Clap subcommand to search plugins in the registry
+
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use log::info;
+use memflow_registry::rest::models::PluginInfo;
+
+use crate::{error::Result, registry};
+
+use super::config::read_config;
+
+#[inline]
+pub fn metadata() -> Command {
+    Command::new("search")
+        .about("searches the plugins available in the registry by name and description")
+        .args([
+        Arg::new("query")
+            .help("search terms which are matched against the plugin name and description, all terms have to match")
+            .required(true)
+            .action(ArgAction::Append),
+        Arg::new("limit")
+            .long("limit")
+            .value_parser(value_parser!(usize))
+            .help("the maximum amount of plugins to show")
+            .action(ArgAction::Set),
+        Arg::new("registry")
+            .short('r')
+            .long("registry")
+            .help("custom registry to use")
+            .action(ArgAction::Set),
+    ])
+}
+
+pub async fn handle(matches: &ArgMatches) -> Result<()> {
+    let config = read_config().await?;
+    let registry = matches
+        .get_one::<String>("registry")
+        .map(String::as_str)
+        .or(config.registry.as_deref());
+    info!("using registry {}", registry::registry_url(registry)?);
+
+    // quoted queries (e.g. `"memory dump"`) are split into separate terms as well
+    let terms = matches
+        .get_many::<String>("query")
+        .unwrap_or_default()
+        .flat_map(|query| query.split_whitespace())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+    let limit = matches.get_one::<usize>("limit").copied();
+
+    let plugins = registry::plugins(registry).await?;
+    let mut results = plugins
+        .iter()
+        .filter_map(|plugin| match_score(plugin, &terms).map(|score| (score, plugin)))
+        .collect::<Vec<_>>();
+    // the registry order is kept for plugins with the same score
+    results.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+
+    println!("{0: <16} DESCRIPTION", "NAME");
+    for (_, plugin) in results.iter().take(limit.unwrap_or(usize::MAX)) {
+        println!("{0: <16} {1}", plugin.name, plugin.description);
+    }
+
+    Ok(())
+}
+
+/// Returns the score of the plugin if all terms match, higher scores are better matches.
+///
+/// Each term has to be contained in the name or description (case-insensitive).
+/// Terms which are not contained in either still match if their characters appear in order in the name (e.g. `cdmp` for `coredump`).
+fn match_score(plugin: &PluginInfo, terms: &[String]) -> Option<usize> {
+    let name = plugin.name.to_lowercase();
+    let description = plugin.description.to_lowercase();
+    terms.iter().try_fold(0, |score, term| {
+        let term_score = if name == *term {
+            4
+        } else if name.contains(term.as_str()) {
+            3
+        } else if description.contains(term.as_str()) {
+            2
+        } else if is_subsequence(term, &name) {
+            1
+        } else {
+            return None;
+        };
+        Some(score + term_score)
+    })
+}
+
+/// Checks if all characters of `needle` appear in `haystack` in the same order.
+fn is_subsequence(needle: &str, haystack: &str) -> bool {
+    let mut haystack = haystack.chars();
+    needle.chars().all(|c| haystack.any(|h| h == c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plugin(name: &str, description: &str) -> PluginInfo {
+        PluginInfo {
+            name: name.to_owned(),
+            description: description.to_owned(),
+        }
+    }
+
+    fn terms(query: &str) -> Vec<String> {
+        query.split_whitespace().map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn match_score_requires_all_terms() {
+        let coredump = plugin("coredump", "win32 coredump connector");
+
+        assert!(match_score(&coredump, &terms("coredump connector")).is_some());
+        assert_eq!(match_score(&coredump, &terms("coredump qemu")), None);
+        assert_eq!(match_score(&coredump, &terms("qemu")), None);
+    }
+
+    #[test]
+    fn match_score_ranks_name_matches_first() {
+        let terms = terms("qemu");
+        let exact = match_score(&plugin("qemu", "qemu connector"), &terms).unwrap();
+        let name = match_score(&plugin("qemu_procfs", "connector"), &terms).unwrap();
+        let description = match_score(&plugin("kvm", "qemu and kvm"), &terms).unwrap();
+        let fuzzy = match_score(&plugin("quickemu", "connector"), &terms).unwrap();
+
+        assert!(exact > name && name > description && description > fuzzy);
+    }
+
+    #[test]
+    fn subsequence() {
+        assert!(is_subsequence("cdmp", "coredump"));
+        assert!(is_subsequence("", "coredump"));
+        assert!(!is_subsequence("dumpcore", "coredump"));
+        assert!(!is_subsequence("coredumps", "coredump"));
+    }
+}
@@ -0,0 +1,205 @@
+//! Clap subcommand to update plugins installed from a registry
+
+use std::{collections::BTreeMap, path::Path};
+
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use futures_util::StreamExt;
+
+use crate::{
+    error::{Error, Result},
+    events::{self, Event},
+    util::{self, InstallSource, LocalPlugin},
+};
+
+use super::{
+    config::read_config,
+    plugins::remove_old_plugin_versions,
+    pull::{self, PullOptions},
+};
+
+#[inline]
+pub fn metadata() -> Command {
+    Command::new("update")
+        .about("updates plugins installed from a registry to their latest version")
+        .args([
+            Arg::new("plugin_name")
+                .help("names of the plugins to update, all installed plugins are updated if none is given")
+                .action(ArgAction::Append),
+            Arg::new("registry")
+                .short('r')
+                .long("registry")
+                .help("pulls the updates from a custom registry")
+                .action(ArgAction::Set),
+            Arg::new("pub-key")
+                .short('p')
+                .long("pub-key")
+                .help("public key used to verify the binary signature (this is required for self-hosted registries)")
+                .action(ArgAction::Set),
+            Arg::new("include-prerelease")
+                .long("include-prerelease")
+                .alias("pre")
+                .help("includes prerelease versions when resolving the latest version")
+                .action(ArgAction::SetTrue),
+            Arg::new("jobs")
+                .short('j')
+                .long("jobs")
+                .value_parser(value_parser!(usize))
+                .default_value("4")
+                .help("the amount of plugins to update concurrently")
+                .action(ArgAction::Set),
+            Arg::new("clean")
+                .long("clean")
+                .help("removes all but the newest version of the updated plugins after updating")
+                .action(ArgAction::SetTrue),
+            Arg::new("yes")
+                .short('y')
+                .long("yes")
+                .help("removes the old versions without asking for confirmation")
+                .requires("clean")
+                .action(ArgAction::SetTrue),
+        ])
+}
+
+pub async fn handle(matches: &ArgMatches) -> Result<()> {
+    let config = read_config().await?;
+    let plugin_names = matches
+        .get_many::<String>("plugin_name")
+        .unwrap_or_default()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    let registry = matches
+        .get_one::<String>("registry")
+        .map(String::as_str)
+        .or(config.registry.as_deref());
+    let pub_key_file = matches
+        .get_one::<String>("pub-key")
+        .map(Path::new)
+        .or(config.pub_key_file.as_deref());
+    let include_prerelease =
+        matches.get_flag("include-prerelease") || config.include_prerelease.unwrap_or_default();
+    let jobs = *matches.get_one::<usize>("jobs").unwrap();
+
+    let verifier = pull::signature_verifier(pub_key_file, &config)?;
+
+    let plugins = util::local_plugins().await?;
+    for plugin_name in plugin_names.iter() {
+        if !plugins
+            .iter()
+            .any(|plugin| plugin.descriptor.name == *plugin_name)
+        {
+            println!(
+                "{} Plugin `{}` is not installed",
+                console::style("[X]").bold().dim().red(),
+                plugin_name,
+            );
+            return Err(Error::NotFound(format!(
+                "plugin `{}` is not installed",
+                plugin_name
+            )));
+        }
+    }
+
+    // plugins are updated per prefix so the new versions are installed with the same prefix again
+    let mut updates: BTreeMap<Option<String>, Vec<String>> = BTreeMap::new();
+    for plugin in plugins.iter() {
+        if !plugin_names.is_empty() && !plugin_names.contains(&plugin.descriptor.name.as_str()) {
+            continue;
+        }
+        if !is_updatable(plugin) {
+            continue;
+        }
+        let names = updates.entry(plugin.prefix.clone()).or_default();
+        if !names.contains(&plugin.descriptor.name) {
+            names.push(plugin.descriptor.name.clone());
+        }
+    }
+
+    if updates.is_empty() {
+        println!(
+            "{} No plugins installed from a registry found.",
+            console::style("[-]").bold().dim(),
+        );
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for (prefix, plugin_names) in updates.iter() {
+        // variants matching an installed plugin are skipped without downloading them again
+        let options = PullOptions {
+            registry,
+            force: false,
+            verifier: Some(&verifier),
+            include_prerelease,
+            all_archs: false,
+            prefix: prefix.as_deref(),
+            installed: plugins
+                .iter()
+                .filter(|plugin| plugin.prefix == *prefix)
+                .filter(|plugin| plugin.plugin_file_name.exists())
+                .map(|plugin| (plugin.digest.clone(), plugin.plugin_file_name.clone()))
+                .collect(),
+            lockfile: None,
+            dry_run: false,
+            versioned_file_names: config.versioned_file_names.unwrap_or(true),
+        };
+
+        let options = &options;
+        let mut pulls = futures_util::stream::iter(plugin_names.iter())
+            .map(|plugin_name| async move { (plugin_name, pull::pull(plugin_name, options).await) })
+            .buffer_unordered(jobs.max(1));
+        while let Some((plugin_name, result)) = pulls.next().await {
+            if let Err(err) = result {
+                events::emit(Event::Error {
+                    plugin: plugin_name,
+                    message: err.to_string(),
+                });
+                failed += 1;
+            }
+        }
+    }
+
+    if matches.get_flag("clean") {
+        // only the old versions of the updated plugins (with their prefix) are removed
+        let removed = remove_old_plugin_versions(
+            1,
+            |plugin| {
+                is_updatable(plugin)
+                    && updates
+                        .get(&plugin.prefix)
+                        .map(|names| names.contains(&plugin.descriptor.name))
+                        .unwrap_or(false)
+            },
+            matches.get_flag("yes"),
+        )
+        .await?;
+        println!(
+            "{} Removed {} old plugin versions.",
+            console::style("[=]").bold().dim().green(),
+            removed,
+        );
+    }
+
+    if failed > 0 {
+        return Err(Error::Unknown(format!(
+            "{} plugins failed to update",
+            failed
+        )));
+    }
+    Ok(())
+}
+
+/// Checks if the plugin was pulled from a registry for the host architecture.
+///
+/// Plugins built from source cannot be updated from the registry and plugins for other
+/// architectures (see `pull --arch all`) would be replaced with the host variant.
+fn is_updatable(plugin: &LocalPlugin) -> bool {
+    if matches!(
+        plugin.installed_via,
+        Some(InstallSource::Source | InstallSource::Local)
+    ) {
+        return false;
+    }
+
+    plugin.descriptor.file_type == util::host_file_type()
+        && plugin.descriptor.architecture == util::host_architecture()
+}
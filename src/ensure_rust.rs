@@ -1,41 +1,42 @@
 use std::{
     ffi::OsStr,
+    path::PathBuf,
     process::{Command, Stdio},
 };
 
 use inquire::Confirm;
 
-use crate::error::Result;
+use crate::{
+    error::{Error, Result},
+    util,
+};
+
+/// Oldest rust version memflow plugins can be built with (the `rust-version` of memflow 0.2)
+const MINIMUM_RUST_VERSION: &str = "1.74.0";
 
 /// Checks if cargo / rust installed properly or installs it
 pub async fn ensure_rust() -> Result<()> {
     match which::which("cargo") {
         Ok(cargo_dir) => {
             println!("cargo found at {:?}", cargo_dir);
-            // TODO: check rust version
-            Ok(())
+            ensure_rust_version()
         }
         Err(_) => {
             println!("cargo not found");
-            if !cfg!(windows) {
-                let install_rustup = {
-                    let ans = Confirm::new("Do you want to install rust via rustup now?")
-                        .with_default(true)
-                        .with_help_message(
-                            "Some components require additional third-party libraries to be built from source.",
-                        )
-                        .prompt();
-
-                    matches!(ans, Ok(true) | Err(_))
-                };
-
-                if install_rustup {
-                    log::info!("cargo not found, installing via rustup");
-                    install_rust().await
-                } else {
-                    println!("rust/cargo not found. please install it manually.");
-                    Err("rust/cargo not found. please install it manually.".into())
-                }
+            let install_rustup = {
+                let ans = Confirm::new("Do you want to install rust via rustup now?")
+                    .with_default(true)
+                    .with_help_message(
+                        "Some components require additional third-party libraries to be built from source.",
+                    )
+                    .prompt();
+
+                matches!(ans, Ok(true) | Err(_))
+            };
+
+            if install_rustup {
+                log::info!("cargo not found, installing via rustup");
+                install_rust().await
             } else {
                 println!("rust/cargo not found. please install it manually.");
                 Err("rust/cargo not found. please install it manually.".into())
@@ -44,32 +45,121 @@ pub async fn ensure_rust() -> Result<()> {
     }
 }
 
-// TODO: windows / mac support
-/// Downloads and executes rustup or panics
+/// Checks if the installed toolchain is recent enough and offers to update it via rustup otherwise.
+fn ensure_rust_version() -> Result<()> {
+    let minimum_version = semver::Version::parse(MINIMUM_RUST_VERSION).unwrap();
+    let version = match cargo_version() {
+        Some(version) => version,
+        None => {
+            // the build itself will report a proper error in case cargo is not working
+            log::warn!("unable to determine the cargo version");
+            return Ok(());
+        }
+    };
+    if version >= minimum_version {
+        return Ok(());
+    }
+
+    println!(
+        "{} Rust {} is installed, but at least rust {} is required to build memflow plugins.",
+        console::style("[-]").bold().dim().yellow(),
+        version,
+        minimum_version
+    );
+    match which::which("rustup") {
+        Ok(rustup_path) => {
+            let update = {
+                let ans = Confirm::new(
+                    "Do you want to update the stable toolchain via `rustup update stable` now?",
+                )
+                .with_default(true)
+                .prompt();
+
+                matches!(ans, Ok(true) | Err(_))
+            };
+
+            if update {
+                let output = std::process::Command::new(rustup_path)
+                    .arg("update")
+                    .arg("stable")
+                    .stdin(Stdio::inherit())
+                    .stdout(Stdio::inherit())
+                    .stderr(Stdio::inherit())
+                    .output()?;
+                util::check_output(output, "rustup update")?;
+                Ok(())
+            } else {
+                println!("Please update rust manually via `rustup update stable` (in case the project pins an older toolchain update the `rust-toolchain` file as well).");
+                Err(Error::Unknown(format!(
+                    "rust {} or newer is required",
+                    minimum_version
+                )))
+            }
+        }
+        Err(_) => {
+            println!(
+                "Please update rust to {} or newer via your package manager or install it via rustup (https://rustup.rs).",
+                minimum_version
+            );
+            Err(Error::Unknown(format!(
+                "rust {} or newer is required",
+                minimum_version
+            )))
+        }
+    }
+}
+
+/// Parses the version from the output of `cargo --version` (e.g. `cargo 1.80.0 (376290515 2024-07-16)`)
+fn cargo_version() -> Option<semver::Version> {
+    let output = Command::new("cargo").arg("--version").output().ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let version = stdout.split_whitespace().nth(1)?;
+    semver::Version::parse(version).ok()
+}
+
+/// Installs the stable toolchain via rustup, rustup is downloaded first if it is not installed yet.
 async fn install_rust() -> Result<()> {
     match which::which("rustup") {
         Ok(rustup_path) => {
             println!("rustup found at {:?}", rustup_path);
             install_rust_toolchain(rustup_path)
         }
-        Err(_) if !cfg!(unix) => {
+        Err(_) => {
             println!("rustup is not installed, trying to download");
-            install_rustup().await.and_then(|_| {
-                install_rust_toolchain(
-                    which::which("rustup").expect("No rustup found after installing rustup!"),
-                )
-            })
-        }
-        _ => {
-            println!("rustup is not installed, setup manually!");
-            Err("Please install rustup".into())
+            install_rustup().await?;
+
+            // the installer only adds cargo to the PATH of new shells, so it is added for the current process as well
+            let cargo_bin_dir = cargo_bin_dir().ok_or("unable to find the cargo home directory")?;
+            add_to_path(cargo_bin_dir)?;
+
+            let rustup_path = which::which("rustup")
+                .map_err(|_| "rustup not found after installing it, please install it manually")?;
+            install_rust_toolchain(rustup_path)
         }
     }
 }
 
-// TODO: windows / mac support
+/// Returns the directory rustup installs the cargo binaries into (`$CARGO_HOME/bin` or `~/.cargo/bin`)
+fn cargo_bin_dir() -> Option<PathBuf> {
+    std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".cargo")))
+        .map(|cargo_home| cargo_home.join("bin"))
+}
+
+/// Prepends the directory to the PATH of the current process so spawned commands can find the binaries in it.
+fn add_to_path(dir: PathBuf) -> Result<()> {
+    let mut paths = vec![dir];
+    if let Some(path) = std::env::var_os("PATH") {
+        paths.extend(std::env::split_paths(&path));
+    }
+    let path = std::env::join_paths(paths).map_err(|err| Error::Unknown(err.to_string()))?;
+    std::env::set_var("PATH", path);
+    Ok(())
+}
+
 fn install_rust_toolchain<P: AsRef<OsStr>>(path: P) -> Result<()> {
-    std::process::Command::new(path)
+    let output = std::process::Command::new(path)
         .arg("toolchain")
         .arg("install")
         .arg("stable")
@@ -78,32 +168,80 @@ fn install_rust_toolchain<P: AsRef<OsStr>>(path: P) -> Result<()> {
         .stderr(Stdio::inherit())
         .output()
         .map_err(|_| "failed to install stable toolchain via rustup")?;
+    util::check_output(output, "rustup toolchain install")?;
 
     Ok(())
 }
 
+/// Downloads and runs the rustup installer script (linux and macos).
+#[cfg(unix)]
 async fn install_rustup() -> Result<()> {
     let mut rustup_path = std::env::temp_dir();
     rustup_path.push("rustup.sh");
 
-    let response = reqwest::get("https://sh.rustup.rs").await?;
+    let response = util::http_client()?
+        .get("https://sh.rustup.rs")
+        .send()
+        .await?;
     tokio::fs::write(rustup_path.clone(), response.text().await?).await?;
 
     // TODO: use libc here
-    Command::new("chmod")
+    let output = Command::new("chmod")
         .arg("+x")
         .arg(rustup_path.clone())
         .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
+        .stderr(Stdio::piped())
         .output()?;
+    util::check_output(output, "chmod")?;
 
-    Command::new("sh")
+    let output = Command::new("sh")
         .arg("-c")
         .arg(rustup_path)
         .stdin(Stdio::inherit())
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit())
         .output()?;
+    util::check_output(output, "rustup installer")?;
+
+    Ok(())
+}
+
+/// Downloads and runs `rustup-init.exe` for the host architecture.
+#[cfg(windows)]
+async fn install_rustup() -> Result<()> {
+    let target = if cfg!(target_arch = "x86_64") {
+        "x86_64-pc-windows-msvc"
+    } else if cfg!(target_arch = "x86") {
+        "i686-pc-windows-msvc"
+    } else if cfg!(target_arch = "aarch64") {
+        "aarch64-pc-windows-msvc"
+    } else {
+        return Err(
+            "rustup is not available for this architecture, please install rust manually".into(),
+        );
+    };
+
+    let mut rustup_path = std::env::temp_dir();
+    rustup_path.push("rustup-init.exe");
+
+    let response = util::http_client()?
+        .get(format!(
+            "https://static.rust-lang.org/rustup/dist/{}/rustup-init.exe",
+            target
+        ))
+        .send()
+        .await?
+        .error_for_status()?;
+    tokio::fs::write(&rustup_path, response.bytes().await?).await?;
+
+    // `-y` installs the default (stable) toolchain without prompting
+    let output = Command::new(&rustup_path)
+        .arg("-y")
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()?;
+    util::check_output(output, "rustup installer")?;
 
     Ok(())
 }
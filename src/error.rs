@@ -20,6 +20,8 @@ pub enum Error {
     AlreadyExists(String),
     #[error("Not implemented: {0}")]
     NotImplemented(String),
+    #[error("Timeout: {0}")]
+    Timeout(String),
 
     // External crate error forwards
     #[error("HTTP error: {0}")]
@@ -34,6 +36,44 @@ pub enum Error {
     Zip(String),
 }
 
+impl Error {
+    /// Returns the name of the error variant, e.g. `NotFound`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Unknown(_) => "Unknown",
+            Error::IO(_) => "IO",
+            Error::Parse(_) => "Parse",
+            Error::NotFound(_) => "NotFound",
+            Error::AlreadyExists(_) => "AlreadyExists",
+            Error::NotImplemented(_) => "NotImplemented",
+            Error::Timeout(_) => "Timeout",
+            Error::Http(_) => "Http",
+            Error::Memflow(_) => "Memflow",
+            Error::Registry(_) => "Registry",
+            Error::Signature(_) => "Signature",
+            Error::Zip(_) => "Zip",
+        }
+    }
+
+    /// Returns the error message without the error kind.
+    pub fn message(&self) -> &str {
+        match self {
+            Error::Unknown(msg)
+            | Error::IO(msg)
+            | Error::Parse(msg)
+            | Error::NotFound(msg)
+            | Error::AlreadyExists(msg)
+            | Error::NotImplemented(msg)
+            | Error::Timeout(msg)
+            | Error::Http(msg)
+            | Error::Memflow(msg)
+            | Error::Registry(msg)
+            | Error::Signature(msg)
+            | Error::Zip(msg) => msg,
+        }
+    }
+}
+
 impl From<&str> for Error {
     fn from(err: &str) -> Self {
         Error::Unknown(err.to_owned())
@@ -90,7 +130,12 @@ impl From<memflow_registry::Error> for Error {
 
 impl From<reqwest::Error> for Error {
     fn from(err: reqwest::Error) -> Self {
-        Error::Http(err.to_string())
+        // timeouts are kept as http errors so interrupted downloads are retried
+        if err.is_timeout() {
+            Error::Http(format!("{} (timed out)", err))
+        } else {
+            Error::Http(err.to_string())
+        }
     }
 }
 
@@ -0,0 +1,129 @@
+//! Event sink for human readable or machine readable output
+//!
+//! By default events are rendered as the usual colored console output.
+//! When json events are enabled each event is printed as a single json object per line instead.
+
+use std::{
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use serde::Serialize;
+
+static JSON_EVENTS: AtomicBool = AtomicBool::new(false);
+
+/// Switches the output of all events to json lines.
+pub fn enable_json() {
+    JSON_EVENTS.store(true, Ordering::Relaxed);
+}
+
+/// Returns true if events are emitted as json lines.
+/// Human readable output that is not covered by an event should be suppressed in this case.
+#[inline]
+pub fn is_json() -> bool {
+    JSON_EVENTS.load(Ordering::Relaxed)
+}
+
+/// Significant events emitted by the command handlers
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    DownloadStarted {
+        plugin: &'a str,
+        bytes: Option<u64>,
+    },
+    DownloadProgress {
+        plugin: &'a str,
+        bytes: u64,
+        total: u64,
+    },
+    Verified {
+        plugin: &'a str,
+        digest: &'a str,
+    },
+    Installed {
+        plugin: &'a str,
+        digest: &'a str,
+        path: &'a Path,
+        verified: bool,
+    },
+    Skipped {
+        plugin: &'a str,
+        digest: &'a str,
+        path: &'a Path,
+        reason: &'a str,
+    },
+    /// The plugin would have been written to `path` but `--dry-run` is set
+    Planned {
+        plugin: &'a str,
+        version: &'a str,
+        digest: &'a str,
+        path: &'a Path,
+        bytes: Option<u64>,
+    },
+    Error {
+        plugin: &'a str,
+        message: String,
+    },
+}
+
+/// Emits the event to the currently active sink.
+pub fn emit(event: Event) {
+    if is_json() {
+        if let Ok(line) = serde_json::to_string(&event) {
+            println!("{}", line);
+        }
+    } else {
+        print_pretty(&event);
+    }
+}
+
+fn print_pretty(event: &Event) {
+    match event {
+        // download progress is shown via progress bars on the console
+        Event::DownloadStarted { .. } | Event::DownloadProgress { .. } | Event::Verified { .. } => {
+        }
+        Event::Installed { path, .. } => {
+            println!(
+                "{} Wrote plugin to: {:?}",
+                console::style("[=]").bold().dim().green(),
+                path.as_os_str(),
+            );
+        }
+        Event::Skipped { path, reason, .. } => {
+            println!(
+                "{} Plugin {:?} {}, skipping download.",
+                console::style("[-]").bold().dim().yellow(),
+                path.file_name().unwrap_or_default(),
+                reason
+            );
+        }
+        Event::Planned {
+            plugin,
+            version,
+            digest,
+            path,
+            bytes,
+        } => {
+            println!(
+                "{} Would write plugin {} {} to: {:?} (digest: {}, size: {})",
+                console::style("[-]").bold().dim(),
+                plugin,
+                version,
+                path.as_os_str(),
+                digest,
+                bytes
+                    .map(|bytes| format!("{} bytes", bytes))
+                    .unwrap_or_else(|| "unknown".to_owned()),
+            );
+        }
+        Event::Error { plugin, message } => {
+            println!(
+                "{} Error downloading plugin {:?}: {}",
+                console::style("[X]").bold().dim().red(),
+                plugin,
+                message
+            );
+        }
+    }
+}
@@ -1,11 +1,17 @@
-use reqwest::{Response, Url};
+use reqwest::{
+    header::{AUTHORIZATION, RANGE},
+    RequestBuilder, Response, StatusCode, Url,
+};
 use serde::{Deserialize, Serialize};
 
-use crate::error::{Error, Result};
-
-const USER_AGENT: &str = "memflowup 0.2.0";
+use crate::{
+    commands::config::read_config_strict,
+    error::{Error, Result},
+    util,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
+#[allow(unused)]
 pub struct Release {
     pub url: String,
     pub assets_url: String,
@@ -27,6 +33,7 @@ pub struct Release {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[allow(unused)]
 pub struct Asset {
     pub url: String,
     pub id: u64,
@@ -62,6 +69,26 @@ pub struct Commit {
     pub sha: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PullRequest {
+    pub number: u64,
+    pub title: String,
+    pub user: User,
+    pub head: PullRequestHead,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PullRequestHead {
+    #[serde(rename = "ref")]
+    pub name: String,
+    pub sha: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct User {
+    pub login: String,
+}
+
 /// Resolves a specific branch from github
 pub async fn branch(url: &str, branch: &str) -> Result<Branch> {
     if !url.contains("github.com") {
@@ -78,12 +105,8 @@ pub async fn branch(url: &str, branch: &str) -> Result<Branch> {
     .parse()
     .unwrap(); // TODO: parse error
 
-    let client = reqwest::Client::new();
-    let response = client
-        .get(path)
-        .header("User-Agent", USER_AGENT)
-        .send()
-        .await?;
+    let client = util::http_client()?;
+    let response = send(client.get(path)).await?.error_for_status()?;
     let result = response.json::<Branch>().await?;
     Ok(result)
 }
@@ -104,32 +127,207 @@ pub async fn tag(url: &str, tag: &str) -> Result<Tag> {
     .parse()
     .unwrap(); // TODO: parse error
 
-    let client = reqwest::Client::new();
-    let response = client
-        .get(path)
-        .header("User-Agent", USER_AGENT)
-        .send()
-        .await?;
+    let client = util::http_client()?;
+    let response = send(client.get(path)).await?.error_for_status()?;
     let result = response.json::<Tag>().await?;
     Ok(result)
 }
 
-/// Downloads the code for specific commit in the repository
-pub async fn download_code_for_commit(url: &str, commit: &str) -> Result<Response> {
+/// Resolves a specific pull request from github
+pub async fn pull_request(url: &str, number: u64) -> Result<PullRequest> {
     if !url.contains("github.com") {
         return Err(Error::Http(
             "github api only works with github.com api".to_owned(),
         ));
     }
 
-    let path: Url = format!("{}/archive/{}.zip", url, commit).parse().unwrap(); // TODO: parse error
+    let path: Url = format!(
+        "{}/pulls/{}",
+        url.replace("github.com", "api.github.com/repos"),
+        number
+    )
+    .parse()
+    .unwrap(); // TODO: parse error
+
+    let client = util::http_client()?;
+    let response = send(client.get(path)).await?.error_for_status()?;
+    let result = response.json::<PullRequest>().await?;
+    Ok(result)
+}
+
+/// Returns the token used to authenticate github requests.
+///
+/// The `GITHUB_TOKEN` environment variable takes precedence over the `github_token` config option.
+async fn github_token() -> Option<String> {
+    match std::env::var("GITHUB_TOKEN") {
+        Ok(token) if !token.is_empty() => Some(token),
+        _ => read_config_strict().await.ok()?.github_token,
+    }
+}
 
-    let client = reqwest::Client::new();
-    let response = client
-        .get(path)
-        .header("User-Agent", USER_AGENT)
-        .send()
-        .await?;
+/// Sends a request to github, authenticated with the github token if one is set.
+///
+/// Anonymous requests are limited to 60 per hour, exceeding the rate limit results in a descriptive error.
+async fn send(mut request: RequestBuilder) -> Result<Response> {
+    if let Some(token) = github_token().await {
+        request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+    }
 
+    let response = request.send().await?;
+    let status = response.status();
+    if status == StatusCode::FORBIDDEN || status == StatusCode::TOO_MANY_REQUESTS {
+        let rate_limited = response
+            .headers()
+            .get("x-ratelimit-remaining")
+            .map(|remaining| remaining == "0")
+            .unwrap_or_default();
+        let body = response.text().await.unwrap_or_default();
+        if rate_limited || body.to_lowercase().contains("rate limit") {
+            return Err(Error::Http(
+                "github api rate limit exceeded, set the `GITHUB_TOKEN` environment variable (or `memflowup config set github_token`) to authenticate requests".to_owned(),
+            ));
+        }
+        return Err(Error::Http(format!(
+            "HTTP status {} for github request: {}",
+            status,
+            body.trim()
+        )));
+    }
     Ok(response)
 }
+
+/// Git hosting service a repository is hosted on.
+///
+/// The provider is used to resolve branches and tags to commits and to download source archives when git is not available.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepoProvider {
+    GitHub,
+    GitLab,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct GitLabRef {
+    name: String,
+    commit: GitLabCommit,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct GitLabCommit {
+    id: String,
+}
+
+impl RepoProvider {
+    /// Picks the provider based on the host of the repository url
+    pub fn from_url(url: &str) -> Result<Self> {
+        let host = parse_repository_url(url)?
+            .host_str()
+            .unwrap_or_default()
+            .to_lowercase();
+        if host == "github.com" || host.ends_with(".github.com") {
+            Ok(Self::GitHub)
+        } else if host.contains("gitlab") {
+            Ok(Self::GitLab)
+        } else {
+            Err(Error::Http(format!(
+                "unsupported repository host `{}`, only github.com and gitlab repositories are supported",
+                host
+            )))
+        }
+    }
+
+    /// Resolves the latest commit of a branch
+    pub async fn branch_commit(&self, url: &str, branch_name: &str) -> Result<String> {
+        match self {
+            Self::GitHub => Ok(branch(url, branch_name).await?.commit.sha),
+            Self::GitLab => {
+                let path = gitlab_api_url(url, &["repository", "branches", branch_name])?;
+                Ok(gitlab_ref(path).await?.commit.id)
+            }
+        }
+    }
+
+    /// Resolves the commit a tag points to
+    pub async fn tag_commit(&self, url: &str, tag_name: &str) -> Result<String> {
+        match self {
+            Self::GitHub => Ok(tag(url, tag_name).await?.commit.sha),
+            Self::GitLab => {
+                let path = gitlab_api_url(url, &["repository", "tags", tag_name])?;
+                Ok(gitlab_ref(path).await?.commit.id)
+            }
+        }
+    }
+
+    /// Returns the url of the zip archive containing the code for the commit
+    pub fn archive_url(&self, url: &str, commit: &str) -> Result<Url> {
+        match self {
+            Self::GitHub => {
+                let mut path = parse_repository_url(url)?;
+                path.path_segments_mut()
+                    .map_err(|_| Error::Parse(format!("invalid repository url `{}`", url)))?
+                    .pop_if_empty()
+                    .extend(&["archive", &format!("{}.zip", commit)]);
+                Ok(path)
+            }
+            Self::GitLab => {
+                let mut path = gitlab_api_url(url, &["repository", "archive.zip"])?;
+                path.query_pairs_mut().append_pair("sha", commit);
+                Ok(path)
+            }
+        }
+    }
+
+    /// Downloads the code for specific commit in the repository
+    ///
+    /// If `offset` is not zero only the remaining bytes are requested to resume a previous download.
+    pub async fn download_code_for_commit(
+        &self,
+        url: &str,
+        commit: &str,
+        offset: u64,
+    ) -> Result<Response> {
+        let path = self.archive_url(url, commit)?;
+
+        let client = util::http_client()?;
+        let mut request = client.get(path);
+        if offset > 0 {
+            request = request.header(RANGE, format!("bytes={}-", offset));
+        }
+        let response = match self {
+            Self::GitHub => send(request).await?,
+            Self::GitLab => request.send().await?,
+        };
+
+        Ok(response)
+    }
+}
+
+fn parse_repository_url(url: &str) -> Result<Url> {
+    url.trim_end_matches(".git")
+        .parse()
+        .map_err(|_| Error::Parse(format!("invalid repository url `{}`", url)))
+}
+
+/// Builds an url for the gitlab project api, the project is referenced by its url encoded path (e.g. `group%2Fproject`)
+fn gitlab_api_url(url: &str, segments: &[&str]) -> Result<Url> {
+    let repository = parse_repository_url(url)?;
+    let project = repository.path().trim_matches('/');
+    if project.is_empty() {
+        return Err(Error::Parse(format!("invalid repository url `{}`", url)));
+    }
+
+    let mut path = repository.clone();
+    path.set_query(None);
+    path.path_segments_mut()
+        .map_err(|_| Error::Parse(format!("invalid repository url `{}`", url)))?
+        .clear()
+        .extend(&["api", "v4", "projects", project])
+        .extend(segments);
+    Ok(path)
+}
+
+async fn gitlab_ref(path: Url) -> Result<GitLabRef> {
+    let client = util::http_client()?;
+    let response = client.get(path).send().await?.error_for_status()?;
+    let result = response.json::<GitLabRef>().await?;
+    Ok(result)
+}
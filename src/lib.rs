@@ -0,0 +1,19 @@
+//! memflowup installs and manages memflow plugins.
+//!
+//! The core operations (resolving plugin uris, downloading and verifying plugins,
+//! installing them and listing locally installed plugins) are exposed via the [`api`] module
+//! so other tools can embed memflowup instead of invoking the binary.
+//! The `memflowup` binary is a thin wrapper around the command handlers in [`commands`].
+
+pub mod api;
+pub mod commands;
+mod ensure_rust;
+pub mod error;
+pub mod events;
+mod github_api;
+pub mod lockfile;
+pub mod logging;
+pub mod registry;
+pub mod util;
+
+pub use error::{Error, Result};
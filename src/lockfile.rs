@@ -0,0 +1,51 @@
+//! Lockfile which pins the digests of pulled plugins for reproducible installs
+
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Result, util};
+
+/// File name of the lockfile, it is read from and written to the current working directory.
+pub const LOCKFILE_NAME: &str = "memflowup.lock";
+
+/// The exact plugin variant that was pulled for a plugin
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedPlugin {
+    pub registry: String,
+    pub version: String,
+    pub digest: String,
+}
+
+/// Locked plugins keyed by their plugin name
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Lockfile {
+    plugins: BTreeMap<String, LockedPlugin>,
+}
+
+impl Lockfile {
+    /// Reads the lockfile, an empty lockfile is returned if the file does not exist yet.
+    pub async fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        match tokio::fs::read_to_string(path).await {
+            Ok(content) => Ok(serde_json::from_str(&content)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub async fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        util::write_file_atomic(path, content.as_bytes()).await
+    }
+
+    #[inline]
+    pub fn get(&self, plugin_name: &str) -> Option<&LockedPlugin> {
+        self.plugins.get(plugin_name)
+    }
+
+    #[inline]
+    pub fn insert(&mut self, plugin_name: &str, plugin: LockedPlugin) {
+        self.plugins.insert(plugin_name.to_owned(), plugin);
+    }
+}
@@ -0,0 +1,75 @@
+//! Logger setup
+
+use std::{fs::OpenOptions, path::Path};
+
+use env_logger::{Env, Logger, Target, WriteStyle};
+use log::{Log, Metadata, Record};
+
+use crate::error::{Error, Result};
+
+/// Forwards log records to the console and optionally to a log file.
+struct DualLogger {
+    console: Logger,
+    file: Option<Logger>,
+}
+
+impl Log for DualLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.console.enabled(metadata)
+            || self
+                .file
+                .as_ref()
+                .map(|f| f.enabled(metadata))
+                .unwrap_or_default()
+    }
+
+    fn log(&self, record: &Record) {
+        self.console.log(record);
+        if let Some(file) = &self.file {
+            file.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.console.flush();
+        if let Some(file) = &self.file {
+            file.flush();
+        }
+    }
+}
+
+/// Initializes the global logger.
+///
+/// Console output is controlled via `RUST_LOG` as usual.
+/// If a log file is given all records (at least at info level) are additionally appended to it with timestamps and without colors.
+pub fn init(log_file: Option<&Path>) -> Result<()> {
+    let console = env_logger::Builder::from_default_env().build();
+
+    let file = if let Some(log_file) = log_file {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log_file)?;
+        Some(
+            env_logger::Builder::from_env(Env::default().default_filter_or("info"))
+                .target(Target::Pipe(Box::new(file)))
+                .write_style(WriteStyle::Never)
+                .format_timestamp_millis()
+                .build(),
+        )
+    } else {
+        None
+    };
+
+    let max_level = console.filter().max(
+        file.as_ref()
+            .map(Logger::filter)
+            .unwrap_or(log::LevelFilter::Off),
+    );
+
+    log::set_boxed_logger(Box::new(DualLogger { console, file }))
+        .map_err(|err| Error::Unknown(err.to_string()))?;
+    log::set_max_level(max_level);
+
+    Ok(())
+}
@@ -1,47 +1,146 @@
-mod commands;
-mod ensure_rust;
-mod error;
-mod github_api;
-mod util;
-
-use std::{process::exit, time::Duration};
+use std::{
+    path::{Path, PathBuf},
+    process::exit,
+    time::Duration,
+};
 
 use clap::*;
-use crates_io_api::SyncClient;
+use crates_io_api::AsyncClient;
 use inquire::Confirm;
 
-use error::{Error, Result};
+use memflowup::{
+    commands,
+    error::{Error, Result},
+    events, logging, util,
+};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let matches = parse_args();
+    let mut command = command();
+    let matches = command.get_matches_mut();
+
+    // completion scripts are printed before any checks so no prompt ends up in the script
+    if let Some(("completions", matches)) = matches.subcommand() {
+        return commands::completions::handle(matches, &mut command);
+    }
+
+    let json_errors = matches
+        .get_one::<String>("error-format")
+        .map(|f| f == "json")
+        .unwrap_or_default();
+
+    match run(&matches).await {
+        Err(err) if json_errors => {
+            // machine readable errors are printed to stderr so they do not interfere with the regular output
+            eprintln!(
+                "{}",
+                serde_json::json!({
+                    "error": {
+                        "kind": err.kind(),
+                        "message": err.message(),
+                    }
+                })
+            );
+            exit(1)
+        }
+        result => result,
+    }
+}
+
+async fn run(matches: &ArgMatches) -> Result<()> {
+    // set log level and optional log file first, so the checks below are logged as well
+    logging::init(matches.get_one::<String>("log-file").map(Path::new))?;
 
     // check if we run as root
     check_root()?;
 
+    if matches.get_flag("events") {
+        events::enable_json();
+    }
+
+    // the config file can be overridden per invocation, the flag takes precedence over the environment
+    if let Some(config) = matches
+        .get_one::<PathBuf>("config")
+        .cloned()
+        .or_else(|| std::env::var_os("MEMFLOWUP_CONFIG").map(PathBuf::from))
+    {
+        util::set_config_file_path(config);
+    }
+
+    // timeouts have to be set before the first request, the flag takes precedence over the config file
+    // (an invalid config file is reported by the commands reading it)
+    let config = commands::config::read_config_strict()
+        .await
+        .unwrap_or_default();
+    let timeout = matches.get_one::<Duration>("timeout").copied();
+    util::set_http_timeouts(util::HttpTimeouts {
+        connect: timeout
+            .or(config.connect_timeout())
+            .unwrap_or(util::DEFAULT_CONNECT_TIMEOUT),
+        request: timeout
+            .or(config.request_timeout())
+            .unwrap_or(util::DEFAULT_REQUEST_TIMEOUT),
+    });
+
     // check for update after we parsed the args
     if !matches.get_flag("skip-version-check") {
         #[cfg(not(debug_assertions))]
-        check_for_update().ok();
+        check_for_update().await.ok();
         #[cfg(debug_assertions)]
         println!("Skipping update check in debug mode.");
     }
 
-    // set log level
-    env_logger::init();
+    // the command future is dropped on Ctrl-C or when the deadline is hit,
+    // this kills spawned processes and removes temporary directories and partially written files.
+    let deadline = matches.get_one::<u64>("deadline").copied();
+    tokio::select! {
+        result = handle_command(matches) => result,
+        _ = tokio::signal::ctrl_c() => {
+            println!(
+                "{} Interrupted, aborting.",
+                console::style("[X]").bold().dim().red(),
+            );
+            Err(Error::Unknown("interrupted".to_owned()))
+        }
+        _ = sleep_until_deadline(deadline) => {
+            let deadline = deadline.unwrap_or_default();
+            println!(
+                "{} Command did not finish within the deadline of {}s, aborting.",
+                console::style("[X]").bold().dim().red(),
+                deadline
+            );
+            Err(Error::Timeout(format!(
+                "command did not finish within {}s",
+                deadline
+            )))
+        }
+    }
+}
 
+async fn handle_command(matches: &ArgMatches) -> Result<()> {
     match matches.subcommand() {
         Some(("push", matches)) => commands::push::handle(matches).await,
         Some(("pull", matches)) => commands::pull::handle(matches).await,
         Some(("registry", matches)) => commands::registry::handle(matches).await,
+        Some(("search", matches)) => commands::search::handle(matches).await,
         Some(("plugins", matches)) => commands::plugins::handle(matches).await,
         Some(("build", matches)) => commands::build::handle(matches).await,
         Some(("config", matches)) => commands::config::handle(matches).await,
+        Some(("update", matches)) => commands::update::handle(matches).await,
         _ => Ok(()),
     }
 }
 
-fn parse_args() -> ArgMatches {
+/// Waits for the given amount of seconds or forever if no deadline is set.
+async fn sleep_until_deadline(deadline: Option<u64>) {
+    match deadline {
+        Some(secs) => tokio::time::sleep(Duration::from_secs(secs)).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Builds the full command tree, it is used for parsing the arguments and generating shell completions.
+fn command() -> Command {
     Command::new("memflowup")
         .arg_required_else_help(true)
         .subcommand_required(true)
@@ -52,22 +151,67 @@ fn parse_args() -> ArgMatches {
                 .long("skip-version-check")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("events")
+                .long("events")
+                .help("emits machine readable json events (one per line) instead of the human readable output")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("error-format")
+                .long("error-format")
+                .value_parser(["human", "json"])
+                .default_value("human")
+                .help("format of the error that is printed when a command fails (`json` prints `{\"error\": {\"kind\": ..., \"message\": ...}}` to stderr)")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("deadline")
+                .long("deadline")
+                .value_name("SECS")
+                .value_parser(value_parser!(u64))
+                .help("aborts the command if it did not finish after the given amount of seconds")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_name("PATH")
+                .value_parser(value_parser!(PathBuf))
+                .help("uses the given config file instead of the default one (can also be set via MEMFLOWUP_CONFIG)")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("timeout")
+                .long("timeout")
+                .value_name("DURATION")
+                .value_parser(humantime::parse_duration)
+                .help("sets the connect timeout and the time to wait for new data of all network operations (e.g. `30s`), overrides `connect_timeout` and `request_timeout` of the config")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("log-file")
+                .long("log-file")
+                .help("additionally writes timestamped log output to the given file")
+                .action(ArgAction::Set),
+        )
         .subcommands([
             commands::build::metadata(),
+            commands::completions::metadata(),
             commands::config::metadata(),
             commands::plugins::metadata(),
             commands::pull::metadata(),
             commands::push::metadata(),
             commands::registry::metadata(),
+            commands::search::metadata(),
+            commands::update::metadata(),
         ])
-        .get_matches()
 }
 
 #[allow(unused)]
-fn check_for_update() -> Result<()> {
-    let client = SyncClient::new("memflowup", Duration::from_millis(1000))
-        .map_err(|err| Error::Http(err.to_string()))?;
-    let memflowup = client.get_crate(crate_name!())?;
+async fn check_for_update() -> Result<()> {
+    let client = AsyncClient::with_http_client(util::http_client()?, Duration::from_millis(1000));
+    let memflowup = client.get_crate(crate_name!()).await?;
 
     // find latest non-yanked version
     if let Some(latest_version) = memflowup.versions.iter().find(|v| !v.yanked) {
@@ -0,0 +1,752 @@
+//! Helpers for querying a memflow-registry
+
+use std::{cmp::Reverse, collections::HashSet};
+
+use memflow_registry::storage::PluginMetadata;
+use memflow_registry::{
+    rest::models::{PluginUploadResponse, PluginsFindResponse},
+    PluginInfo, PluginUri, PluginVariant, PluginsAllResponse, MEMFLOW_DEFAULT_REGISTRY,
+};
+use reqwest::{
+    header::{CONTENT_LENGTH, CONTENT_TYPE, LOCATION, RANGE},
+    redirect::Policy,
+    Response, StatusCode, Url,
+};
+use serde::de::DeserializeOwned;
+
+use crate::{
+    error::{Error, Result},
+    util,
+};
+
+/// Amount of recent variants that are taken into account when resolving the `latest` version.
+const LATEST_CANDIDATES: usize = 25;
+
+/// Maximum amount of redirects that are followed when downloading a plugin binary.
+const MAX_REDIRECTS: usize = 10;
+
+/// Maximum amount of characters of a non-json response body that are included in error messages.
+const BODY_SNIPPET_LEN: usize = 200;
+
+/// Describes how the version component of a plugin uri is resolved by the registry.
+///
+/// `coredump` / `coredump:latest` - resolves the newest version
+/// `coredump:0.2.0` - resolves the exact version
+/// `coredump:stable` - resolves the version the registry assigned to the `stable` channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionQuery<'a> {
+    Latest,
+    Version(&'a str),
+    Channel(&'a str),
+}
+
+impl<'a> VersionQuery<'a> {
+    pub fn from_uri(plugin_uri: &'a PluginUri) -> Self {
+        match plugin_uri.version() {
+            "latest" => Self::Latest,
+            version if semver::Version::parse(version).is_ok() => Self::Version(version),
+            channel => Self::Channel(channel),
+        }
+    }
+
+    /// Appends the query parameters for this version query to the url.
+    fn append_query(&self, path: &mut Url) {
+        match self {
+            Self::Latest => (),
+            Self::Version(version) => {
+                path.query_pairs_mut().append_pair("version", version);
+            }
+            Self::Channel(channel) => {
+                path.query_pairs_mut().append_pair("channel", channel);
+            }
+        }
+    }
+}
+
+/// Finds the plugin variant matching the given plugin uri.
+///
+/// For the `latest` version a set of recent variants is fetched and the highest semver version is selected
+/// (falling back to created_at when versions are equal), independently of the order the registry returns them in.
+/// Prerelease versions are skipped for `latest` unless `include_prerelease` is set.
+///
+/// Named channels (e.g. `stable`) are sent to the registry as such, in case the registry
+/// rejects the channel or does not return any variant for it the name is treated as an exact version instead.
+pub async fn find_by_uri(
+    plugin_uri: &PluginUri,
+    all_archs: bool,
+    memflow_plugin_version: Option<i32>,
+    include_prerelease: bool,
+) -> Result<PluginVariant> {
+    let variant = match VersionQuery::from_uri(plugin_uri) {
+        VersionQuery::Latest => {
+            let variants = find_variants(
+                plugin_uri,
+                VersionQuery::Latest,
+                all_archs,
+                memflow_plugin_version,
+                LATEST_CANDIDATES,
+            )
+            .await?;
+            latest_variant(variants, include_prerelease)
+        }
+        VersionQuery::Channel(channel) => {
+            let variants =
+                find_channel_variants(plugin_uri, channel, all_archs, memflow_plugin_version)
+                    .await?;
+            match variants.and_then(|variants| variants.into_iter().next()) {
+                Some(variant) => Some(variant),
+                None => find_variants(
+                    plugin_uri,
+                    VersionQuery::Version(channel),
+                    all_archs,
+                    memflow_plugin_version,
+                    1,
+                )
+                .await?
+                .into_iter()
+                .next(),
+            }
+        }
+        query => find_variants(plugin_uri, query, all_archs, memflow_plugin_version, 1)
+            .await?
+            .into_iter()
+            .next(),
+    };
+
+    variant.ok_or_else(|| {
+        Error::NotFound(format!(
+            "plugin `{}` not found for the current architecture",
+            plugin_uri
+        ))
+    })
+}
+
+/// Finds one variant for every os and architecture of the plugin version matching the given plugin uri.
+///
+/// The version is resolved across all architectures first (see `find_by_uri`),
+/// afterwards the newest variant of that version is selected for each file type and architecture.
+pub async fn find_all_archs_by_uri(
+    plugin_uri: &PluginUri,
+    memflow_plugin_version: Option<i32>,
+    include_prerelease: bool,
+) -> Result<Vec<PluginVariant>> {
+    let resolved =
+        find_by_uri(plugin_uri, true, memflow_plugin_version, include_prerelease).await?;
+
+    let mut variants = find_variants(
+        plugin_uri,
+        VersionQuery::Version(&resolved.descriptor.version),
+        true,
+        memflow_plugin_version,
+        LATEST_CANDIDATES,
+    )
+    .await?;
+    variants.sort_by_key(|variant| Reverse(variant.created_at));
+
+    let mut targets = HashSet::new();
+    variants.retain(|variant| {
+        targets.insert((
+            variant.descriptor.file_type,
+            variant.descriptor.architecture,
+        ))
+    });
+
+    if variants.is_empty() {
+        variants.push(resolved);
+    }
+    Ok(variants)
+}
+
+/// Finds the variant with the given digest among the variants of a specific version of the plugin.
+///
+/// This is used to pull the exact variant recorded in a lockfile.
+pub async fn find_by_version_and_digest(
+    plugin_uri: &PluginUri,
+    version: &str,
+    digest: &str,
+) -> Result<PluginVariant> {
+    find_variants(
+        plugin_uri,
+        VersionQuery::Version(version),
+        true,
+        None,
+        LATEST_CANDIDATES,
+    )
+    .await?
+    .into_iter()
+    .find(|variant| variant.digest == digest)
+    .ok_or_else(|| {
+        Error::NotFound(format!(
+            "plugin `{}` with version {} and digest {} not found",
+            plugin_uri.image(),
+            version,
+            digest
+        ))
+    })
+}
+
+/// Splits a content addressed plugin uri (e.g. `coredump@sha256:<digest>`) into the plugin uri and the digest.
+///
+/// Uris without a digest are returned unchanged.
+pub fn split_digest(plugin_uri: &str) -> Result<(&str, Option<&str>)> {
+    match plugin_uri.split_once("@sha256:") {
+        Some((plugin_uri, digest))
+            if digest.len() == 64 && digest.chars().all(|c| c.is_ascii_hexdigit()) =>
+        {
+            Ok((plugin_uri, Some(digest)))
+        }
+        Some(_) => Err(Error::Parse(format!(
+            "invalid digest in plugin uri `{}`, expected the full sha256 digest",
+            plugin_uri
+        ))),
+        None => Ok((plugin_uri, None)),
+    }
+}
+
+/// Finds the plugin variant with the given digest via the `files/<digest>` endpoint of the registry.
+///
+/// The version of the plugin uri is ignored, the digest already identifies the exact binary.
+pub async fn find_by_digest(plugin_uri: &PluginUri, digest: &str) -> Result<PluginVariant> {
+    let path = file_url(plugin_uri, &digest.to_lowercase(), "/metadata")?;
+
+    let response = util::http_client()?.get(path).send().await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Err(Error::NotFound(format!(
+            "plugin with digest {} not found in registry",
+            digest
+        )));
+    }
+    let metadata = read_json::<PluginMetadata>(response).await?;
+
+    // a binary can contain multiple plugins, the descriptor of the requested one is used
+    let descriptor = metadata
+        .descriptors
+        .into_iter()
+        .find(|descriptor| descriptor.name == plugin_uri.image())
+        .ok_or_else(|| {
+            Error::NotFound(format!(
+                "plugin binary with digest {} does not contain plugin `{}`",
+                digest,
+                plugin_uri.image()
+            ))
+        })?;
+
+    Ok(PluginVariant {
+        digest: metadata.digest,
+        signature: metadata.signature,
+        created_at: metadata.created_at,
+        descriptor,
+    })
+}
+
+/// Queries the registry for all variants of the plugin matching the version query.
+async fn find_variants(
+    plugin_uri: &PluginUri,
+    query: VersionQuery<'_>,
+    all_archs: bool,
+    memflow_plugin_version: Option<i32>,
+    limit: usize,
+) -> Result<Vec<PluginVariant>> {
+    let path = find_url(plugin_uri, query, all_archs, memflow_plugin_version, limit)?;
+
+    let response = util::http_client()?.get(path).send().await?;
+    let response = read_json::<PluginsFindResponse>(response).await?;
+
+    Ok(response.plugins)
+}
+
+/// Finds the newest variant assigned to the given channel.
+///
+/// Returns `None` if the registry rejects the channel query (e.g. because it does not support channels),
+/// network and server errors are returned as is.
+async fn find_channel_variants(
+    plugin_uri: &PluginUri,
+    channel: &str,
+    all_archs: bool,
+    memflow_plugin_version: Option<i32>,
+) -> Result<Option<Vec<PluginVariant>>> {
+    let path = find_url(
+        plugin_uri,
+        VersionQuery::Channel(channel),
+        all_archs,
+        memflow_plugin_version,
+        1,
+    )?;
+
+    let response = util::http_client()?.get(path).send().await?;
+    if response.status().is_client_error() {
+        return Ok(None);
+    }
+    let response = read_json::<PluginsFindResponse>(response).await?;
+
+    Ok(Some(response.plugins))
+}
+
+/// Constructs the query url used to find variants of a plugin.
+fn find_url(
+    plugin_uri: &PluginUri,
+    query: VersionQuery<'_>,
+    all_archs: bool,
+    memflow_plugin_version: Option<i32>,
+    limit: usize,
+) -> Result<Url> {
+    // bare hosts (e.g. `myregistry.io/coredump`) are normalized the same way as the configured registry
+    let mut path = registry_url(Some(plugin_uri.registry()))?;
+    path.set_path(&format!("plugins/{}", plugin_uri.image()));
+
+    // setup filtering based on the os memflowup is built for
+    query.append_query(&mut path);
+    {
+        let mut query = path.query_pairs_mut();
+        if let Some(memflow_plugin_version) = memflow_plugin_version {
+            query.append_pair(
+                "memflow_plugin_version",
+                &memflow_plugin_version.to_string(),
+            );
+        }
+        query.append_pair("limit", &limit.to_string());
+    }
+    if !all_archs {
+        append_os_arch_filter(&mut path);
+    }
+
+    Ok(path)
+}
+
+/// Result of checking a bearer token against the registry, see `check_token`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenStatus {
+    /// The registry accepted the token.
+    Valid,
+    /// The registry rejected the token.
+    Invalid,
+    /// The registry accepts any token, so no token is required.
+    NotRequired,
+    /// The response of the registry does not tell whether the token is accepted.
+    Unknown(StatusCode),
+}
+
+/// Checks if the registry accepts the given bearer token.
+///
+/// The registry does not provide an identity endpoint, so an upload with an empty signature and without a file is sent instead.
+/// The registry checks the token before rejecting the incomplete upload with `file is required`,
+/// so nothing is stored on or removed from the registry.
+/// The probe is sent with an invalid token first to detect registries that do not require a token.
+pub async fn check_token(registry: Option<&str>, token: &str) -> Result<TokenStatus> {
+    let mut path = registry_url(registry)?;
+    path.set_path("files");
+
+    if probe_token(&path, "memflowup-invalid-token").await? == TokenStatus::Valid {
+        return Ok(TokenStatus::NotRequired);
+    }
+    probe_token(&path, token).await
+}
+
+/// Sends an empty upload with the given token, see `check_token`.
+async fn probe_token(path: &Url, token: &str) -> Result<TokenStatus> {
+    let response = util::http_client()?
+        .post(path.clone())
+        .bearer_auth(token)
+        .multipart(reqwest::multipart::Form::new().text("signature", ""))
+        .send()
+        .await?;
+
+    let status = response.status();
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Ok(TokenStatus::Invalid),
+        // only the rejection of the upload itself proves that the token was accepted
+        StatusCode::BAD_REQUEST => {
+            let body = response.text().await.unwrap_or_default();
+            if body.contains("file is required") {
+                Ok(TokenStatus::Valid)
+            } else {
+                Ok(TokenStatus::Unknown(status))
+            }
+        }
+        _ => Ok(TokenStatus::Unknown(status)),
+    }
+}
+
+/// Queries the metadata of the plugin binary.
+pub async fn metadata(plugin_uri: &PluginUri, variant: &PluginVariant) -> Result<PluginMetadata> {
+    let path = file_url(plugin_uri, &variant.digest, "/metadata")?;
+
+    let response = util::http_client()?.get(path).send().await?;
+    read_json(response).await
+}
+
+/// Parses the json body of a registry response.
+///
+/// Misconfigured registries, proxies or captive portals might answer with html pages instead,
+/// in this case the status code and the beginning of the body are reported instead of a serde error.
+async fn read_json<T: DeserializeOwned>(response: Response) -> Result<T> {
+    let is_json = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.contains("json"))
+        .unwrap_or_default();
+    if is_json {
+        return Ok(response.error_for_status()?.json::<T>().await?);
+    }
+
+    let status = response.status();
+    let url = response.url().clone();
+    let body = response.text().await.unwrap_or_default();
+    let snippet = body
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(BODY_SNIPPET_LEN)
+        .collect::<String>();
+    if snippet.is_empty() && !status.is_success() {
+        return Err(Error::Http(format!(
+            "HTTP status {} for url ({})",
+            status, url
+        )));
+    }
+    Err(Error::Registry(format!(
+        "registry at {} returned a non-json response ({}): {}",
+        url, status, snippet
+    )))
+}
+
+/// Starts the download of the plugin binary.
+///
+/// Registries might redirect the download to a different location (e.g. a presigned object storage or cdn url).
+/// Redirects are followed manually so a missing digest in the registry can be distinguished from a failing storage backend.
+///
+/// If `offset` is not zero only the remaining bytes are requested to resume a previous download.
+pub async fn download(
+    plugin_uri: &PluginUri,
+    variant: &PluginVariant,
+    offset: u64,
+) -> Result<Response> {
+    let client = util::http_client_builder()
+        .redirect(Policy::none())
+        .build()?;
+
+    let mut path = file_url(plugin_uri, &variant.digest, "")?;
+    for redirects in 0..=MAX_REDIRECTS {
+        let mut request = client.get(path.clone());
+        if offset > 0 {
+            request = request.header(RANGE, format!("bytes={}-", offset));
+        }
+        let response = request.send().await?;
+
+        let status = response.status();
+        if status.is_redirection() {
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .ok_or_else(|| Error::Http(format!("redirect from {} without a location", path)))?;
+            // the location might be relative to the current url
+            path = path
+                .join(location)
+                .map_err(|_| Error::Http(format!("invalid redirect location `{}`", location)))?;
+            log::debug!("following redirect to {}", path);
+            continue;
+        }
+
+        if status == StatusCode::NOT_FOUND {
+            return Err(if redirects == 0 {
+                Error::NotFound(format!(
+                    "plugin binary with digest `{}` not found in registry",
+                    variant.digest
+                ))
+            } else {
+                Error::Http(format!(
+                    "plugin binary with digest `{}` not found at redirected location {}",
+                    variant.digest, path
+                ))
+            });
+        }
+
+        // the range is not satisfiable if the previously downloaded part is already complete
+        if offset > 0 && status == StatusCode::RANGE_NOT_SATISFIABLE {
+            return Ok(response);
+        }
+
+        return Ok(response.error_for_status()?);
+    }
+
+    Err(Error::Http(format!(
+        "too many redirects while downloading plugin `{}`",
+        variant.digest
+    )))
+}
+
+/// Constructs the url of the plugin binary (or one of its sub resources).
+fn file_url(plugin_uri: &PluginUri, digest: &str, suffix: &str) -> Result<Url> {
+    let mut path = registry_url(Some(plugin_uri.registry()))?;
+    path.set_path(&format!("files/{}{}", digest, suffix));
+    Ok(path)
+}
+
+/// Queries the size of the plugin binary via a `HEAD` request without downloading it.
+/// Returns `None` if the registry does not report a content length.
+pub async fn download_size(plugin_uri: &PluginUri, variant: &PluginVariant) -> Result<Option<u64>> {
+    let path = file_url(plugin_uri, &variant.digest, "")?;
+
+    let response = util::http_client()?
+        .head(path)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    // HEAD responses do not have a body, so the header has to be parsed manually
+    Ok(response
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok()))
+}
+
+/// Explicit file type and architecture filter for registry queries (e.g. `elf` and `arm64`)
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PlatformFilter<'a> {
+    pub file_type: Option<&'a str>,
+    pub architecture: Option<&'a str>,
+}
+
+/// Valid values for the file type filter
+pub const FILE_TYPES: [&str; 3] = ["pe", "elf", "mach"];
+
+/// Valid values for the architecture filter
+pub const ARCHITECTURES: [&str; 4] = ["x86", "x86_64", "arm", "arm64"];
+
+/// Retrieves a list of all plugins in the registry and their descriptions.
+pub async fn plugins(registry: Option<&str>) -> Result<Vec<PluginInfo>> {
+    let mut path = registry_url(registry)?;
+    path.set_path("plugins");
+
+    let response = util::http_client()?.get(path).send().await?;
+    let response = read_json::<PluginsAllResponse>(response).await?;
+
+    Ok(response.plugins)
+}
+
+/// Lists the most recent variants of a plugin.
+///
+/// By default only variants for the os and architecture memflowup is built for are returned,
+/// `all_archs` removes this filter and the `filter` explicitly overrides it.
+pub async fn plugin_versions(
+    registry: Option<&str>,
+    plugin_name: &str,
+    all_archs: bool,
+    filter: PlatformFilter<'_>,
+    limit: usize,
+) -> Result<Vec<PluginVariant>> {
+    let mut path = registry_url(registry)?;
+    path.set_path(&format!("plugins/{}", plugin_name));
+    path.query_pairs_mut()
+        .append_pair("limit", &limit.to_string());
+    append_platform_filter(&mut path, all_archs, filter);
+
+    let response = util::http_client()?.get(path).send().await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Err(Error::NotFound(format!(
+            "plugin `{}` not found in registry",
+            plugin_name
+        )));
+    }
+    let response = read_json::<PluginsFindResponse>(response).await?;
+
+    Ok(response.plugins)
+}
+
+/// Uploads a plugin binary together with its signature to the registry.
+///
+/// Unlike `memflow_registry::client::upload` the signature is computed by the caller,
+/// this allows signing via an external signer instead of a private key on disk.
+pub async fn upload(
+    registry: Option<&str>,
+    token: Option<&str>,
+    file_name: &str,
+    file_content: Vec<u8>,
+    signature: String,
+) -> Result<PluginUploadResponse> {
+    let mut path = registry_url(registry)?;
+    path.set_path("files");
+
+    let file_part = reqwest::multipart::Part::bytes(file_content)
+        .file_name(file_name.to_owned())
+        .mime_str("application/octet-stream")?;
+    let form = reqwest::multipart::Form::new()
+        .part("file", file_part)
+        .text("signature", signature);
+
+    let mut builder = util::http_client()?.post(path);
+    if let Some(token) = token {
+        builder = builder.bearer_auth(token);
+    }
+
+    let response = builder.multipart(form).send().await?;
+    if response.status().is_success() {
+        read_json(response).await
+    } else {
+        Err(Error::Http(response.text().await?))
+    }
+}
+
+/// Deletes the plugin binary with the given digest from the registry.
+pub async fn delete(registry: Option<&str>, token: Option<&str>, digest: &str) -> Result<()> {
+    let mut path = registry_url(registry)?;
+    path.set_path(&format!("files/{}", digest));
+
+    let mut builder = util::http_client()?.delete(path);
+    if let Some(token) = token {
+        builder = builder.bearer_auth(token);
+    }
+
+    let response = builder.send().await?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(Error::Http(response.text().await?))
+    }
+}
+
+/// Parses the registry url and defaults to the memflow registry.
+/// Registries without a scheme are accessed via https.
+pub fn registry_url(registry: Option<&str>) -> Result<Url> {
+    let mut registry = registry.unwrap_or(MEMFLOW_DEFAULT_REGISTRY).to_owned();
+    if !registry.starts_with("http://") && !registry.starts_with("https://") {
+        registry = format!("https://{}", registry);
+    }
+    registry
+        .parse()
+        .map_err(|_| Error::Parse(format!("invalid registry url `{}`", registry)))
+}
+
+/// Returns the variant with the highest semver version and the newest created_at timestamp.
+/// Versions that cannot be parsed as semver are ordered before all valid versions and are treated as stable.
+pub fn latest_variant(
+    mut variants: Vec<PluginVariant>,
+    include_prerelease: bool,
+) -> Option<PluginVariant> {
+    if !include_prerelease {
+        variants.retain(|variant| !is_prerelease(&variant.descriptor.version));
+    }
+
+    variants.sort_by_key(|variant| {
+        Reverse((
+            semver::Version::parse(&variant.descriptor.version).ok(),
+            variant.created_at,
+        ))
+    });
+    variants.into_iter().next()
+}
+
+/// Returns true if the version is a valid semver prerelease version (e.g. `0.3.0-beta.1`).
+#[inline]
+pub fn is_prerelease(version: &str) -> bool {
+    semver::Version::parse(version)
+        .map(|v| !v.pre.is_empty())
+        .unwrap_or_default()
+}
+
+/// Filters the query by the os and architecture memflowup is built for.
+fn append_os_arch_filter(path: &mut Url) {
+    append_platform_filter(path, false, PlatformFilter::default());
+}
+
+/// Appends the file type and architecture filter to the query.
+/// Explicitly set values take precedence over the os and architecture memflowup is built for.
+fn append_platform_filter(path: &mut Url, all_archs: bool, filter: PlatformFilter<'_>) {
+    let (host_file_type, host_architecture) = if all_archs {
+        (None, None)
+    } else {
+        (host_file_type_filter(), host_architecture_filter())
+    };
+
+    let mut query = path.query_pairs_mut();
+    if let Some(file_type) = filter.file_type.or(host_file_type) {
+        query.append_pair("file_type", file_type);
+    }
+    if let Some(architecture) = filter.architecture.or(host_architecture) {
+        query.append_pair("architecture", architecture);
+    }
+}
+
+/// Returns the file type filter of the os memflowup is built for.
+fn host_file_type_filter() -> Option<&'static str> {
+    if cfg!(target_os = "windows") {
+        Some("pe")
+    } else if cfg!(target_os = "linux") {
+        Some("elf")
+    } else if cfg!(target_os = "macos") {
+        Some("mach")
+    } else {
+        None
+    }
+}
+
+/// Returns the architecture filter of the architecture memflowup is built for.
+fn host_architecture_filter() -> Option<&'static str> {
+    if cfg!(target_arch = "x86_64") {
+        Some("x86_64")
+    } else if cfg!(target_arch = "x86") {
+        Some("x86")
+    } else if cfg!(target_arch = "aarch64") {
+        Some("arm64")
+    } else if cfg!(target_arch = "arm") {
+        Some("arm")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query_pairs(plugin_uri: &str) -> Vec<(String, String)> {
+        let plugin_uri: PluginUri = plugin_uri.parse().unwrap();
+        find_url(
+            &plugin_uri,
+            VersionQuery::from_uri(&plugin_uri),
+            true,
+            None,
+            1,
+        )
+        .unwrap()
+        .query_pairs()
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect()
+    }
+
+    #[test]
+    fn find_url_exact_version() {
+        let pairs = query_pairs("coredump:1.2.3");
+        assert!(pairs.contains(&("version".to_owned(), "1.2.3".to_owned())));
+        assert!(!pairs.iter().any(|(key, _)| key == "channel"));
+    }
+
+    #[test]
+    fn find_url_channel() {
+        let pairs = query_pairs("coredump:stable");
+        assert!(pairs.contains(&("channel".to_owned(), "stable".to_owned())));
+        assert!(!pairs.iter().any(|(key, _)| key == "version"));
+        assert_ne!(pairs, query_pairs("coredump:1.2.3"));
+    }
+
+    #[test]
+    fn find_url_latest() {
+        let pairs = query_pairs("coredump");
+        assert!(!pairs
+            .iter()
+            .any(|(key, _)| key == "version" || key == "channel"));
+    }
+
+    #[test]
+    fn find_url_bare_host_registry() {
+        let plugin_uri: PluginUri = "myregistry.io/coredump".parse().unwrap();
+        let url = find_url(&plugin_uri, VersionQuery::Latest, true, None, 1).unwrap();
+        assert_eq!(url.scheme(), "https");
+        assert_eq!(url.host_str(), Some("myregistry.io"));
+        assert_eq!(url.path(), "/plugins/coredump");
+    }
+}
@@ -1,27 +1,35 @@
 use std::cmp::Reverse;
 use std::fs::{self, File};
+use std::future::Future;
 use std::io::{self};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Output, Stdio};
+use std::process::{Output, Stdio};
+use std::sync::OnceLock;
+use std::time::Duration;
 
-use bytes::{Bytes, BytesMut};
-use chrono::NaiveDateTime;
+use bytes::Bytes;
+use chrono::{NaiveDateTime, SubsecRound};
 use futures_util::StreamExt;
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use log::{debug, info, warn};
-use memflow::plugins::plugin_analyzer::PluginDescriptorInfo;
+use memflow::plugins::plugin_analyzer::{PluginArchitecture, PluginDescriptorInfo, PluginFileType};
 use memflow_registry::storage::PluginMetadata;
 use memflow_registry::PluginUri;
-use reqwest::Response;
+use reqwest::{Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
 use zip::ZipArchive;
 
-use crate::error::{Error, Result};
+use crate::{
+    error::{Error, Result},
+    events::{self, Event},
+};
 
 /// Returns the path in which memflow plugins are stored.
 ///
 /// On unix this is returns ~/.local/lib/memflow
 /// On windows this returns C:\Users\[Username]\Documents\memflow
-pub(crate) fn plugins_path() -> PathBuf {
+pub fn plugins_path() -> PathBuf {
     let path = if cfg!(unix) {
         dirs::home_dir()
             .unwrap()
@@ -56,13 +64,26 @@ pub(crate) fn config_path() -> PathBuf {
     path
 }
 
+static CONFIG_FILE_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Overrides the config file used for the remainder of this run (e.g. via `--config` or `MEMFLOWUP_CONFIG`).
+pub fn set_config_file_path(path: PathBuf) {
+    CONFIG_FILE_PATH.set(path).ok();
+}
+
 /// Returns the path that points to the memflowup config.
 #[inline]
 pub(crate) fn config_file_path() -> PathBuf {
-    let file_path = config_path().join("config.json");
+    let file_path = CONFIG_FILE_PATH
+        .get()
+        .cloned()
+        .unwrap_or_else(|| config_path().join("config.json"));
 
     // ensure config file exists and contains valid json
     if !file_path.exists() {
+        if let Some(parent) = file_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).expect("unable to create config directory");
+        }
         std::fs::write(&file_path, b"{}").expect("unable to write config file");
     }
 
@@ -71,75 +92,557 @@ pub(crate) fn config_file_path() -> PathBuf {
 
 /// Constructs the filename of this plugin for the current os.
 ///
-/// On unix this returns libmemflow_[name]_[digest].so/.dylib
-/// On windows this returns memflow_[name]_[digest].dll
-pub(crate) fn plugin_file_name(metadata: &PluginMetadata) -> PathBuf {
-    let mut file_name = plugins_path();
-
-    // prepend the library name and append the file digest
-    if cfg!(unix) {
-        file_name.push(format!(
-            "libmemflow_{}_{}",
-            metadata
-                .descriptors
-                .first()
-                .map(|d| d.name.clone())
-                .unwrap_or_else(|| "unknown".to_string()),
-            &metadata.digest[..7]
-        ))
+/// On unix this returns libmemflow_[name]_[version]_[digest].so/.dylib
+/// On windows this returns memflow_[name]_[version]_[digest].dll
+///
+/// The digest is shortened to 7 characters unless this would collide with a different installed plugin.
+/// If a `prefix` is given it is prepended to the file name, e.g. [prefix]_libmemflow_[name]_[version]_[digest].so
+/// The version is omitted if `include_version` is not set (see the `versioned_file_names` config option).
+pub(crate) fn plugin_file_name(
+    metadata: &PluginMetadata,
+    prefix: Option<&str>,
+    include_version: bool,
+) -> PathBuf {
+    plugin_file_name_in(&plugins_path(), metadata, prefix, include_version)
+}
+
+/// Constructs the filename of this plugin for the current os inside of the given directory.
+///
+/// See `plugin_file_name` for the naming scheme.
+pub(crate) fn plugin_file_name_in(
+    dir: &Path,
+    metadata: &PluginMetadata,
+    prefix: Option<&str>,
+    include_version: bool,
+) -> PathBuf {
+    let (name, version) = metadata
+        .descriptors
+        .first()
+        .map(|d| (d.name.clone(), sanitize_file_name(&d.version)))
+        .unwrap_or_else(|| ("unknown".to_string(), "unknown".to_string()));
+
+    // prepend the library name and append the version and file digest.
+    // the extension is added manually as set_extension() would replace parts of the version.
+    unique_plugin_file_name(dir, &metadata.digest, prefix, |digest| {
+        format!(
+            "{}memflow_{}_{}{}.{}",
+            if cfg!(unix) { "lib" } else { "" },
+            name,
+            version_part(&version, include_version),
+            digest,
+            memflow::plugins::plugin_extension()
+        )
+    })
+}
+
+/// Returns the path that a plugin variant of an arbitrary os and architecture will be stored in.
+///
+/// Unlike `plugin_file_name` the file type and architecture are part of the file name
+/// so variants for different targets can coexist, e.g. libmemflow_[name]_[version]_elf_x86_64_[digest].so
+pub(crate) fn plugin_file_name_with_arch(
+    metadata: &PluginMetadata,
+    prefix: Option<&str>,
+    include_version: bool,
+) -> PathBuf {
+    let Some(descriptor) = metadata.descriptors.first() else {
+        return plugin_file_name(metadata, prefix, include_version);
+    };
+
+    let (lib_prefix, extension) = file_type_prefix_and_extension(descriptor.file_type);
+
+    unique_plugin_file_name(&plugins_path(), &metadata.digest, prefix, |digest| {
+        format!(
+            "{}memflow_{}_{}{}_{}.{}",
+            lib_prefix,
+            descriptor.name,
+            version_part(&sanitize_file_name(&descriptor.version), include_version),
+            format!("{:?}_{:?}", descriptor.file_type, descriptor.architecture)
+                .to_ascii_lowercase(),
+            digest,
+            extension
+        )
+    })
+}
+
+/// Returns the version followed by a separator or an empty string if the version is omitted from the file name.
+fn version_part(version: &str, include_version: bool) -> String {
+    if include_version {
+        format!("{}_", version)
+    } else {
+        String::new()
+    }
+}
+
+/// Returns the library prefix and file extension used for plugin binaries of the given file type.
+fn file_type_prefix_and_extension(file_type: PluginFileType) -> (&'static str, &'static str) {
+    match file_type {
+        PluginFileType::Pe => ("", "dll"),
+        PluginFileType::Elf => ("lib", "so"),
+        PluginFileType::Mach => ("lib", "dylib"),
+    }
+}
+
+/// Checks if the file in the plugins directory is a plugin binary.
+///
+/// Plugins for other targets (see `pull --arch all`) use the extension of their file type,
+/// so the extension has to match the file type in the .meta file. Files without a readable
+/// .meta file are considered plugins if they have the extension of any supported file type.
+pub(crate) fn is_plugin_file(path: &Path) -> bool {
+    let Some(extension) = path.extension().and_then(|ext| ext.to_str()) else {
+        return false;
+    };
+
+    let mut meta_file_name = path.to_path_buf();
+    meta_file_name.set_extension("meta");
+    match read_plugin_meta(&meta_file_name)
+        .ok()
+        .and_then(|meta| meta.metadata.descriptors.into_iter().next())
+    {
+        Some(descriptor) => extension == file_type_prefix_and_extension(descriptor.file_type).1,
+        None => [
+            PluginFileType::Elf,
+            PluginFileType::Pe,
+            PluginFileType::Mach,
+        ]
+        .into_iter()
+        .any(|file_type| extension == file_type_prefix_and_extension(file_type).1),
+    }
+}
+
+/// Returns the plugin path with the short digest in its file name.
+///
+/// If a different plugin (according to its .meta file) is already installed under the same short digest
+/// the full digest is used instead so neither plugin is overwritten.
+fn unique_plugin_file_name(
+    dir: &Path,
+    digest: &str,
+    prefix: Option<&str>,
+    file_name: impl Fn(&str) -> String,
+) -> PathBuf {
+    let file_name = |digest: &str| match prefix {
+        Some(prefix) => format!("{}_{}", prefix, file_name(digest)),
+        None => file_name(digest),
+    };
+    let path = dir.join(file_name(&digest[..7]));
+    if !path.exists() {
+        return path;
+    }
+
+    let mut meta_file_name = path.clone();
+    meta_file_name.set_extension("meta");
+    match read_plugin_meta(&meta_file_name) {
+        Ok(existing) if existing.metadata.digest == digest => path,
+        // a binary without a readable .meta file is the same plugin only if the file digest matches
+        Err(_) if sha256_file(&path).ok().as_deref() == Some(digest) => path,
+        _ => {
+            warn!(
+                "short digest of plugin {} collides with {:?}, using the full digest",
+                digest, path
+            );
+            dir.join(file_name(digest))
+        }
+    }
+}
+
+/// Returns the user agent that is sent with all outbound http requests,
+/// e.g. `memflowup/0.2.0 (linux; x86_64)`.
+pub fn user_agent() -> String {
+    format!(
+        "memflowup/{} ({}; {})",
+        clap::crate_version!(),
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    )
+}
+
+/// Default time to wait for a connection to be established.
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default time to wait for new data on an established connection.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Timeouts applied to all http requests
+#[derive(Debug, Clone, Copy)]
+pub struct HttpTimeouts {
+    pub connect: Duration,
+    /// Applies to every read, so large downloads are not capped as long as data keeps arriving
+    pub request: Duration,
+}
+
+impl Default for HttpTimeouts {
+    fn default() -> Self {
+        Self {
+            connect: DEFAULT_CONNECT_TIMEOUT,
+            request: DEFAULT_REQUEST_TIMEOUT,
+        }
+    }
+}
+
+static HTTP_TIMEOUTS: OnceLock<HttpTimeouts> = OnceLock::new();
+
+/// Sets the timeouts for the remainder of this run, this has to be called before the first request is sent.
+pub fn set_http_timeouts(timeouts: HttpTimeouts) {
+    HTTP_TIMEOUTS.set(timeouts).ok();
+}
+
+/// Returns a client builder with the settings shared by all http requests.
+///
+/// Proxies are picked up from the `HTTP_PROXY`, `HTTPS_PROXY` and `ALL_PROXY` environment variables,
+/// hosts listed in `NO_PROXY` are accessed directly.
+/// A stalled connection fails with a timeout error, interrupted downloads are resumed by `download_resumable`.
+pub fn http_client_builder() -> reqwest::ClientBuilder {
+    let timeouts = HTTP_TIMEOUTS.get().copied().unwrap_or_default();
+    reqwest::Client::builder()
+        .user_agent(user_agent())
+        .connect_timeout(timeouts.connect)
+        .read_timeout(timeouts.request)
+}
+
+/// Returns the http client shared by all requests (see `http_client_builder`).
+pub fn http_client() -> Result<reqwest::Client> {
+    static HTTP_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    if let Some(client) = HTTP_CLIENT.get() {
+        return Ok(client.clone());
+    }
+    let client = http_client_builder().build()?;
+    Ok(HTTP_CLIENT.get_or_init(|| client).clone())
+}
+
+/// Returns the binary file type of plugins for the os memflowup is built for.
+pub(crate) fn host_file_type() -> PluginFileType {
+    if cfg!(target_os = "windows") {
+        PluginFileType::Pe
+    } else if cfg!(target_os = "macos") {
+        PluginFileType::Mach
+    } else {
+        PluginFileType::Elf
+    }
+}
+
+/// Returns the plugin architecture memflowup is built for.
+pub(crate) fn host_architecture() -> PluginArchitecture {
+    if cfg!(target_arch = "x86_64") {
+        PluginArchitecture::X86_64
+    } else if cfg!(target_arch = "x86") {
+        PluginArchitecture::X86
+    } else if cfg!(target_arch = "aarch64") {
+        PluginArchitecture::Arm64
+    } else if cfg!(target_arch = "arm") {
+        PluginArchitecture::Arm
     } else {
-        file_name.push(format!(
-            "memflow_{}_{}",
-            metadata
-                .descriptors
-                .first()
-                .map(|d| d.name.clone())
-                .unwrap_or_else(|| "unknown".to_string()),
-            &metadata.digest[..7]
+        PluginArchitecture::Unknown(0)
+    }
+}
+
+/// Parses the `--prefix` argument, only characters which are safe to be used in a file name are allowed.
+pub(crate) fn parse_prefix(prefix: &str) -> std::result::Result<String, String> {
+    if !prefix.is_empty() && sanitize_file_name(prefix) == prefix {
+        Ok(prefix.to_owned())
+    } else {
+        Err(format!(
+            "invalid prefix `{}`, only alphanumeric characters, `.`, `-` and `_` are allowed",
+            prefix
         ))
     }
+}
 
-    // append appropriate file extension
-    file_name.set_extension(memflow::plugins::plugin_extension());
+/// Replaces all characters which are not safe to be used in a file name.
+fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
 
-    file_name
+/// Returns the progress bar container shared by all concurrent downloads
+/// so their progress bars are rendered below each other instead of interleaving.
+pub(crate) fn multi_progress() -> &'static MultiProgress {
+    static MULTI_PROGRESS: OnceLock<MultiProgress> = OnceLock::new();
+    MULTI_PROGRESS.get_or_init(MultiProgress::new)
 }
 
-pub async fn read_response_with_progress(response: Response) -> Result<Bytes> {
-    let mut buffer = BytesMut::new();
+/// Amount of times an interrupted download is resumed before giving up.
+const DOWNLOAD_RETRIES: usize = 3;
+
+/// Downloads into `part_path` while showing a progress bar and returns the downloaded content.
+///
+/// `request` is called with the amount of bytes that are already downloaded and has to request the remaining bytes
+/// via a `Range` header. Interrupted transfers are resumed up to `DOWNLOAD_RETRIES` times, bytes left over in `part_path`
+/// from a previous run are reused as well.
+///
+/// The partial file is kept so the download can be resumed later, the caller has to rename or remove it
+/// once the content has been verified. If the server rejects the range of a complete partial file
+/// (`416 Range Not Satisfiable`) its content is returned as is.
+pub async fn download_resumable<F, Fut>(part_path: &Path, name: &str, request: F) -> Result<Bytes>
+where
+    F: Fn(u64) -> Fut,
+    Fut: Future<Output = Result<Response>>,
+{
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(part_path)
+        .await?;
+
+    let mut attempt = 0;
+    loop {
+        // pending writes have to be flushed so the file length reflects all received bytes
+        file.flush().await?;
+        let offset = file.metadata().await?.len();
+        let result = match request(offset).await {
+            // the partial file already contains the whole content (e.g. it was left over by a previous run),
+            // the caller verifies the digest of the content
+            Ok(response)
+                if offset > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE =>
+            {
+                Ok(())
+            }
+            // the server does not support range requests, start from scratch
+            Ok(response) if offset > 0 && response.status() != StatusCode::PARTIAL_CONTENT => {
+                file.set_len(0).await?;
+                write_response_with_progress(response, name, &mut file, 0).await
+            }
+            Ok(response) => write_response_with_progress(response, name, &mut file, offset).await,
+            Err(err) => Err(err),
+        };
+
+        match result {
+            Ok(()) => break,
+            Err(err @ (Error::Http(_) | Error::IO(_))) if attempt < DOWNLOAD_RETRIES => {
+                attempt += 1;
+                file.flush().await?;
+                let offset = file.metadata().await?.len();
+                if !events::is_json() {
+                    println!(
+                        "{} Download of {} was interrupted ({}), resuming at {} bytes (attempt {}/{}).",
+                        console::style("[-]").bold().dim().yellow(),
+                        name,
+                        err,
+                        offset,
+                        attempt,
+                        DOWNLOAD_RETRIES
+                    );
+                }
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    file.flush().await?;
+    file.sync_all().await?;
+    Ok(Bytes::from(tokio::fs::read(part_path).await?))
+}
+
+/// Appends the response body to the file while showing a progress bar.
+/// When json events are enabled the progress bar is hidden and progress events are emitted for every 10% instead.
+/// When stdout is not a terminal (e.g. in ci logs) plain progress lines are printed for every 10% instead.
+///
+/// `offset` is the amount of bytes that were already downloaded before this response.
+async fn write_response_with_progress(
+    response: Response,
+    name: &str,
+    file: &mut tokio::fs::File,
+    offset: u64,
+) -> Result<()> {
+    let plain = !events::is_json() && !console::Term::stdout().is_term();
+    let mut downloaded = offset;
     if let Some(content_length) = response.content_length() {
-        let pb = ProgressBar::new(content_length);
-        pb.set_style(ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})")
+        let total = content_length + offset;
+        let pb = if events::is_json() || plain {
+            ProgressBar::hidden()
+        } else {
+            multi_progress().add(ProgressBar::new(total))
+        };
+        pb.set_style(ProgressStyle::with_template("{spinner:.green} {msg} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})")
                     .unwrap()
                     .progress_chars("#>-"));
+        pb.set_message(name.to_owned());
+        pb.set_position(offset);
 
         // download data in chunks to show progress
+        let mut reported_step = (offset * 10) / total.max(1);
         let mut stream = response.bytes_stream();
         while let Some(chunk) = stream.next().await {
             let chunk = chunk?;
-            buffer.extend_from_slice(chunk.as_ref());
+            file.write_all(chunk.as_ref()).await?;
+            downloaded += chunk.len() as u64;
             pb.inc(chunk.len() as u64);
+
+            let step = (downloaded * 10) / total.max(1);
+            if step > reported_step {
+                reported_step = step;
+                if events::is_json() {
+                    events::emit(Event::DownloadProgress {
+                        plugin: name,
+                        bytes: downloaded,
+                        total,
+                    });
+                } else if plain {
+                    println!(
+                        "{} Downloading {}: {}% ({}/{} bytes)",
+                        console::style("[-]").bold().dim(),
+                        name,
+                        step * 10,
+                        downloaded,
+                        total
+                    );
+                }
+            }
         }
         pb.finish();
+
+        if downloaded < total {
+            return Err(Error::Http(format!(
+                "connection closed after {} of {} bytes",
+                downloaded, total
+            )));
+        }
     } else {
-        // no content-length set, fallback without progress bar
-        warn!("skipping progress bar because content-length is not set");
-        buffer.extend_from_slice(&response.bytes().await?.to_vec()[..]);
+        // no content-length set (e.g. chunked responses from a cdn), only show the amount of downloaded bytes
+        info!("content-length is not set, total download size is unknown");
+        let pb = if events::is_json() || plain {
+            ProgressBar::hidden()
+        } else {
+            multi_progress().add(ProgressBar::new_spinner())
+        };
+        pb.set_style(
+            ProgressStyle::with_template(
+                "{spinner:.green} {msg} [{elapsed_precise}] {bytes} ({bytes_per_sec})",
+            )
+            .unwrap(),
+        );
+        pb.set_message(name.to_owned());
+        pb.set_position(offset);
+
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            file.write_all(chunk.as_ref()).await?;
+            downloaded += chunk.len() as u64;
+            pb.inc(chunk.len() as u64);
+        }
+        pb.finish();
+
+        if plain {
+            println!(
+                "{} Downloaded {}: {} bytes",
+                console::style("[-]").bold().dim(),
+                name,
+                downloaded
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Describes how a plugin was installed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstallSource {
+    /// Pulled from a memflow-registry
+    Registry,
+    /// Built from a remote repository
+    Source,
+    /// Built from a local path
+    Local,
+}
+
+impl std::fmt::Display for InstallSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InstallSource::Registry => write!(f, "registry"),
+            InstallSource::Source => write!(f, "source"),
+            InstallSource::Local => write!(f, "local"),
+        }
+    }
+}
+
+/// Contents of the .meta file stored next to each installed plugin.
+///
+/// This extends the registry metadata with information about the local installation.
+/// The registry fields are flattened so .meta files stay compatible with plain `PluginMetadata`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalPluginMetadata {
+    #[serde(flatten)]
+    pub metadata: PluginMetadata,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub installed_via: Option<InstallSource>,
+    /// Version of memflowup that installed the plugin
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub installed_by_memflowup: Option<String>,
+    /// Plugin ABI version (`MEMFLOW_PLUGIN_VERSION`) of the memflow version memflowup was built against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memflow_plugin_version: Option<i32>,
+    /// Prefix the plugin file name was installed with (see `--prefix`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+}
+
+impl LocalPluginMetadata {
+    /// Wraps the registry metadata and records the current memflowup and memflow plugin ABI versions.
+    pub fn new(metadata: PluginMetadata, installed_via: InstallSource) -> Self {
+        Self {
+            metadata,
+            installed_via: Some(installed_via),
+            installed_by_memflowup: Some(clap::crate_version!().to_owned()),
+            memflow_plugin_version: Some(memflow::plugins::MEMFLOW_PLUGIN_VERSION),
+            prefix: None,
+        }
     }
-    Ok(buffer.freeze())
+}
+
+/// Reads and parses a .meta file.
+///
+/// Older .meta files might be missing fields that are required by the current format.
+/// In this case the missing `signature` is treated as unsigned and `created_at` is derived
+/// from the modification time of the .meta file.
+pub fn read_plugin_meta<P: AsRef<Path>>(meta_file_name: P) -> Result<LocalPluginMetadata> {
+    let meta_file_name = meta_file_name.as_ref();
+    let content = fs::read_to_string(meta_file_name)?;
+    if let Ok(metadata) = serde_json::from_str::<LocalPluginMetadata>(&content) {
+        return Ok(metadata);
+    }
+
+    // lenient parse: fill in fields which can be derived
+    let mut value = serde_json::from_str::<serde_json::Value>(&content)?;
+    let object = value
+        .as_object_mut()
+        .ok_or_else(|| Error::Parse("invalid .meta file".to_owned()))?;
+    object
+        .entry("signature")
+        .or_insert_with(|| serde_json::Value::String(String::new()));
+    if !object.contains_key("created_at") {
+        let modified: chrono::DateTime<chrono::Utc> =
+            fs::metadata(meta_file_name)?.modified()?.into();
+        object.insert(
+            "created_at".to_owned(),
+            serde_json::to_value(modified.naive_utc().trunc_subsecs(0))?,
+        );
+    }
+    Ok(serde_json::from_value(value)?)
 }
 
 /// Describes a locally installed plugin
-#[derive(Clone)]
+#[derive(Clone, Serialize)]
 pub struct LocalPlugin {
     pub plugin_file_name: PathBuf,
     pub meta_file_name: PathBuf,
     pub digest: String,
+    pub signature: String,
     pub created_at: NaiveDateTime,
     pub descriptor: PluginDescriptorInfo,
+    pub installed_via: Option<InstallSource>,
+    pub installed_by_memflowup: Option<String>,
+    pub memflow_plugin_version: Option<i32>,
+    pub prefix: Option<String>,
 }
 
-/// Returns a list of all local plugins with their .meta information attached (sorted in the same way as memflow-registry)
+/// Returns a list of all local plugins with their .meta information attached (sorted in the same way as memflow-registry, ties are broken by digest)
 pub async fn local_plugins() -> Result<Vec<LocalPlugin>> {
     let mut result = Vec::new();
 
@@ -148,48 +651,83 @@ pub async fn local_plugins() -> Result<Vec<LocalPlugin>> {
         if let Some(extension) = path.path().extension() {
             if extension.to_str().unwrap_or_default() == "meta" {
                 let meta_file_name = path.path();
-                if let Ok(metadata) = serde_json::from_str::<PluginMetadata>(
-                    &tokio::fs::read_to_string(&meta_file_name).await?,
-                ) {
-                    let mut plugin_file_name = meta_file_name.clone();
-                    plugin_file_name.set_extension(memflow::plugins::plugin_extension());
-
-                    // TODO: additionally check existence of the file name and pass it over
-                    for descriptor in metadata.descriptors.into_iter() {
-                        result.push(LocalPlugin {
-                            plugin_file_name: plugin_file_name.clone(),
-                            meta_file_name: meta_file_name.clone(),
-                            digest: metadata.digest.clone(),
-                            created_at: metadata.created_at,
-                            descriptor,
-                        });
+                match read_plugin_meta(&meta_file_name) {
+                    Ok(LocalPluginMetadata {
+                        metadata,
+                        installed_via,
+                        installed_by_memflowup,
+                        memflow_plugin_version,
+                        prefix,
+                    }) => {
+                        // plugins for other targets (see `pull --arch all`) use the extension of their file type
+                        let extension = metadata
+                            .descriptors
+                            .first()
+                            .map(|descriptor| {
+                                file_type_prefix_and_extension(descriptor.file_type).1
+                            })
+                            .unwrap_or_else(memflow::plugins::plugin_extension);
+                        let mut plugin_file_name = meta_file_name.clone();
+                        plugin_file_name.set_extension(extension);
+
+                        // TODO: additionally check existence of the file name and pass it over
+                        for descriptor in metadata.descriptors.into_iter() {
+                            result.push(LocalPlugin {
+                                plugin_file_name: plugin_file_name.clone(),
+                                meta_file_name: meta_file_name.clone(),
+                                digest: metadata.digest.clone(),
+                                signature: metadata.signature.clone(),
+                                created_at: metadata.created_at,
+                                descriptor,
+                                installed_via,
+                                installed_by_memflowup: installed_by_memflowup.clone(),
+                                memflow_plugin_version,
+                                prefix: prefix.clone(),
+                            });
+                        }
+                    }
+                    Err(err) => {
+                        // printed to stderr so the output of commands like `plugins which` stays usable in scripts
+                        eprintln!(
+                            "{} Unable to read plugin metadata {:?} ({}), run `memflowup plugins repair` to recreate it.",
+                            console::style("[-]").bold().dim().yellow(),
+                            meta_file_name,
+                            err
+                        );
                     }
-                } else {
-                    // TODO: print warning about orphaned plugin and give hints
-                    // on how to install plugins from source with memflowup
                 }
             }
         }
     }
 
-    // sort by plugin_name, plugin_version and created_at
-    result.sort_by_key(|plugin| {
-        (
-            plugin.descriptor.name.clone(),
-            Reverse(plugin.descriptor.plugin_version),
-            Reverse(plugin.created_at),
-        )
-    });
+    result.sort_by(|a, b| local_plugin_sort_key(a).cmp(&local_plugin_sort_key(b)));
 
     Ok(result)
 }
 
+/// Sorts by plugin_name, plugin_version and created_at (digest is used as a tiebreaker to keep the order stable).
+fn local_plugin_sort_key(
+    plugin: &LocalPlugin,
+) -> (&str, Reverse<i32>, Reverse<NaiveDateTime>, &str) {
+    (
+        &plugin.descriptor.name,
+        Reverse(plugin.descriptor.plugin_version),
+        Reverse(plugin.created_at),
+        &plugin.digest,
+    )
+}
+
 /// Finds a locally installed plugin based on the given plugin uri.
-pub async fn find_local_plugin(plugin_uri_str: &str) -> Result<LocalPlugin> {
+pub async fn find_local_plugin(plugin_uri_str: &str, prefix: Option<&str>) -> Result<LocalPlugin> {
     let plugin_uri: PluginUri = plugin_uri_str.parse()?;
 
     let plugins = local_plugins().await?;
     for plugin in plugins.into_iter() {
+        // optionally only match plugins installed with the given file name prefix
+        if prefix.is_some() && plugin.prefix.as_deref() != prefix {
+            continue;
+        }
+
         // we match the following cases here:
         // plugin_uri is a digest
         // plugin_uri is {name}:{version}
@@ -211,6 +749,38 @@ pub async fn find_local_plugin(plugin_uri_str: &str) -> Result<LocalPlugin> {
     )))
 }
 
+/// Reads a list of entries from a file with one entry per line.
+/// Empty lines and lines starting with `#` are ignored.
+pub async fn read_list_file<P: AsRef<Path>>(path: P) -> Result<Vec<String>> {
+    let content = tokio::fs::read_to_string(path).await?;
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect())
+}
+
+/// Reads a list of plugin uris from a file.
+/// Lines that are not valid plugin uris are reported and skipped.
+pub async fn read_plugin_uris_file<P: AsRef<Path>>(path: P) -> Result<Vec<String>> {
+    let mut plugin_uris = Vec::new();
+    for line in read_list_file(path).await?.into_iter() {
+        match line.parse::<PluginUri>() {
+            Ok(_) => plugin_uris.push(line),
+            Err(err) => {
+                println!(
+                    "{} Skipping invalid plugin uri `{}`: {}",
+                    console::style("[X]").bold().dim().red(),
+                    line,
+                    err
+                );
+            }
+        }
+    }
+    Ok(plugin_uris)
+}
+
 /// Unpack zip archive in memory
 pub fn zip_unpack(in_buf: &[u8], out_dir: &Path, strip_path: i64) -> crate::Result<()> {
     let zip_cursor = std::io::Cursor::new(in_buf);
@@ -260,21 +830,96 @@ pub fn zip_unpack(in_buf: &[u8], out_dir: &Path, strip_path: i64) -> crate::Resu
     Ok(())
 }
 
-/// Executes cargo with the given flags
-pub fn cargo<P: AsRef<Path>>(args: &str, pwd: P) -> Result<Output> {
+/// Checks the exit status of a finished command.
+/// If the command failed the captured stderr output (if it was piped) is included in the returned error.
+pub fn check_output(output: Output, description: &str) -> Result<Output> {
+    if output.status.success() {
+        return Ok(output);
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stderr = stderr.trim();
+    if stderr.is_empty() {
+        Err(Error::IO(format!(
+            "{} failed ({})",
+            description, output.status
+        )))
+    } else {
+        Err(Error::IO(format!(
+            "{} failed ({}): {}",
+            description, output.status, stderr
+        )))
+    }
+}
+
+/// Executes cargo with the given flags and additional environment variables.
+/// Returns an error if cargo exits with a non-zero status code.
+/// Stdout is captured so machine readable output can be parsed, cargo prints its progress to stderr.
+/// The cargo process is killed if the returned future is dropped (e.g. on Ctrl-C or when the deadline is hit).
+pub async fn cargo<P: AsRef<Path>>(
+    args: &str,
+    pwd: P,
+    envs: &[(String, String)],
+) -> Result<Output> {
     log::info!("executing 'cargo {}' in {:?}", args, pwd.as_ref());
-    let mut cmd = Command::new("cargo");
+    let mut cmd = tokio::process::Command::new("cargo");
 
     cmd.current_dir(pwd)
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit());
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true);
+
+    for (key, value) in envs.iter() {
+        // values are not logged as they might contain credentials
+        log::info!("setting environment variable '{}'", key);
+        cmd.env(key, value);
+    }
 
     for arg in args.split(' ') {
         cmd.arg(arg);
     }
 
-    let output = cmd.output()?;
-    Ok(output)
+    let output = cmd.output().await?;
+    check_output(output, &format!("cargo {}", args))
+}
+
+/// Computes the sha256 digest of a file.
+///
+/// The file is streamed through the hasher in small chunks so memory usage
+/// stays bounded regardless of the size of the plugin.
+pub fn sha256_file<P: AsRef<Path>>(path: P) -> Result<String> {
+    Ok(sha256::try_digest(path.as_ref())?)
+}
+
+/// Writes the file via a temporary `.part` file in the same directory which is renamed once all contents are written and synced to disk.
+///
+/// If the future is dropped before it completes the partially written file is removed again,
+/// this ensures no truncated plugins are left behind in case the operation is aborted.
+/// The temporary file name contains the process id so concurrent memflowup instances do not write into the same file.
+pub async fn write_file_atomic<P: AsRef<Path>>(path: P, contents: &[u8]) -> Result<()> {
+    let path = path.as_ref();
+    let mut part_path = path.as_os_str().to_owned();
+    part_path.push(format!(".{}.part", std::process::id()));
+    let part = PartialFile(PathBuf::from(part_path));
+
+    let mut file = tokio::fs::File::create(&part.0).await?;
+    file.write_all(contents).await?;
+    file.flush().await?;
+    file.sync_all().await?;
+    drop(file);
+
+    // renaming is atomic on the same filesystem, readers either see the old or the new file
+    tokio::fs::rename(&part.0, path).await?;
+    Ok(())
+}
+
+/// Removes the contained file when dropped (a no-op if the file has already been moved).
+struct PartialFile(PathBuf);
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.0).ok();
+    }
 }
 
 /// Create a temporary directory, but it can already be an existing one.
@@ -287,6 +932,13 @@ pub async fn create_temp_dir(subdir: &str, uid: &str) -> crate::Result<TempDir>
 
 pub struct TempDir(PathBuf);
 
+impl TempDir {
+    /// Consumes the temporary directory without deleting it and returns its path.
+    pub fn persist(mut self) -> PathBuf {
+        std::mem::take(&mut self.0)
+    }
+}
+
 impl std::ops::Deref for TempDir {
     type Target = PathBuf;
 
@@ -309,6 +961,172 @@ impl std::fmt::Debug for TempDir {
 
 impl Drop for TempDir {
     fn drop(&mut self) {
-        std::fs::remove_dir_all(&self.0).expect("cannot delete the tmp dir")
+        // the path is empty if the directory has been persisted
+        if !self.0.as_os_str().is_empty() {
+            std::fs::remove_dir_all(&self.0).expect("cannot delete the tmp dir")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    fn sh(script: &str) -> Output {
+        std::process::Command::new("sh")
+            .args(["-c", script])
+            .output()
+            .unwrap()
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn check_output_success() {
+        let output = check_output(sh("echo hello"), "sh").unwrap();
+        assert_eq!(output.stdout, b"hello\n");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn check_output_failure() {
+        assert!(check_output(sh("exit 3"), "sh").is_err());
+
+        let err = check_output(sh("echo oops >&2; exit 3"), "sh").unwrap_err();
+        assert!(err.to_string().contains("oops"));
+    }
+
+    fn plugin_metadata(digest: &str) -> PluginMetadata {
+        serde_json::from_value(serde_json::json!({
+            "digest": digest,
+            "signature": "",
+            "created_at": "2024-01-01T00:00:00",
+            "descriptors": [{
+                "plugin_kind": "connector",
+                "export_name": "MEMFLOW_CONNECTOR_COREDUMP",
+                "file_type": "elf",
+                "architecture": "x86_64",
+                "plugin_version": 1,
+                "name": "coredump",
+                "version": "0.2.0",
+                "description": "coredump connector",
+            }],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn plugin_file_name_short_digest_collision() {
+        let dir =
+            std::env::temp_dir().join(format!("memflowup-test-collision-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let first_digest = format!("abcdef0{}", "1".repeat(57));
+        let second_digest = format!("abcdef0{}", "2".repeat(57));
+
+        // install the first plugin under its short digest
+        let first = plugin_metadata(&first_digest);
+        let first_path = plugin_file_name_in(&dir, &first, None, true);
+        let first_file_name = first_path.file_name().unwrap().to_string_lossy();
+        assert!(first_file_name.contains("_abcdef0.") && !first_file_name.contains(&first_digest));
+        fs::write(&first_path, b"first").unwrap();
+        fs::write(
+            first_path.with_extension("meta"),
+            serde_json::to_string(&LocalPluginMetadata::new(first, InstallSource::Registry))
+                .unwrap(),
+        )
+        .unwrap();
+
+        // the same plugin keeps its file name, a different plugin falls back to the full digest
+        assert_eq!(
+            plugin_file_name_in(&dir, &plugin_metadata(&first_digest), None, true),
+            first_path
+        );
+        let second_path = plugin_file_name_in(&dir, &plugin_metadata(&second_digest), None, true);
+        assert_ne!(second_path, first_path);
+        assert!(second_path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .contains(&second_digest));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn local_plugin(digest: &str) -> LocalPlugin {
+        let metadata = plugin_metadata(digest);
+        LocalPlugin {
+            plugin_file_name: PathBuf::new(),
+            meta_file_name: PathBuf::new(),
+            digest: metadata.digest,
+            signature: metadata.signature,
+            created_at: metadata.created_at,
+            descriptor: metadata.descriptors.into_iter().next().unwrap(),
+            installed_via: None,
+            installed_by_memflowup: None,
+            memflow_plugin_version: None,
+            prefix: None,
+        }
+    }
+
+    #[test]
+    fn local_plugin_sort_key_breaks_ties_by_digest() {
+        let digests = ["c".repeat(64), "a".repeat(64), "b".repeat(64)];
+
+        let mut plugins: Vec<_> = digests.iter().map(|digest| local_plugin(digest)).collect();
+        plugins.sort_by(|a, b| local_plugin_sort_key(a).cmp(&local_plugin_sort_key(b)));
+        let sorted: Vec<_> = plugins.iter().map(|plugin| plugin.digest.clone()).collect();
+
+        // the order does not depend on the order the .meta files were read in
+        let mut reversed: Vec<_> = digests
+            .iter()
+            .rev()
+            .map(|digest| local_plugin(digest))
+            .collect();
+        reversed.sort_by(|a, b| local_plugin_sort_key(a).cmp(&local_plugin_sort_key(b)));
+
+        assert_eq!(sorted, ["a".repeat(64), "b".repeat(64), "c".repeat(64)]);
+        assert!(reversed
+            .iter()
+            .map(|plugin| &plugin.digest)
+            .eq(sorted.iter()));
+    }
+
+    #[test]
+    fn is_plugin_file_uses_meta_file_type() {
+        let dir =
+            std::env::temp_dir().join(format!("memflowup-test-plugin-file-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        // a windows plugin pulled via `--arch all`
+        let mut metadata = serde_json::to_value(plugin_metadata(&"a".repeat(64))).unwrap();
+        metadata["descriptors"][0]["file_type"] = "pe".into();
+        fs::write(dir.join("memflow_coredump.meta"), metadata.to_string()).unwrap();
+
+        assert!(is_plugin_file(&dir.join("memflow_coredump.dll")));
+        assert!(!is_plugin_file(&dir.join("memflow_coredump.so")));
+
+        // without a .meta file every plugin extension is accepted
+        assert!(is_plugin_file(&dir.join("libmemflow_qemu.dylib")));
+        assert!(!is_plugin_file(&dir.join("libmemflow_qemu.txt")));
+        assert!(!is_plugin_file(&dir.join("memflow_coredump.meta")));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn plugin_file_name_without_version() {
+        let dir =
+            std::env::temp_dir().join(format!("memflowup-test-version-{}", std::process::id()));
+        let metadata = plugin_metadata(&"a".repeat(64));
+
+        let versioned = plugin_file_name_in(&dir, &metadata, None, true);
+        let versioned = versioned.file_name().unwrap().to_string_lossy();
+        assert!(versioned.contains("memflow_coredump_0.2.0_aaaaaaa."));
+
+        let unversioned = plugin_file_name_in(&dir, &metadata, Some("proj"), false);
+        let unversioned = unversioned.file_name().unwrap().to_string_lossy();
+        assert!(unversioned.starts_with("proj_"));
+        assert!(unversioned.contains("memflow_coredump_aaaaaaa."));
     }
 }